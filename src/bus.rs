@@ -0,0 +1,141 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+
+use dbus;
+use dbus::channel::{BusType, Channel};
+use users::get_current_uid;
+
+static DBUS_BAD_ADDRESS_ERROR: &str = "org.freedesktop.DBus.Error.BadAddress";
+
+/// Which bus to mount.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub enum Bus {
+  #[default]
+  System,
+  Session,
+  /// The bus that activated us, see `DBUS_STARTER_ADDRESS`.
+  Starter,
+  /// An address with an optional name for its directory.
+  Address(Option<String>, String),
+}
+
+impl Bus {
+  /// Parse the argument of `--address`, `[NAME=]ADDRESS`. An address has a colon
+  /// before its first `=`, so a leading name is never mistaken for a part of it.
+  pub fn from_address(arg: &str) -> Bus {
+    match arg.find('=') {
+      Some(eq) if !arg[..eq].contains(':') => Bus::Address(Some(arg[..eq].to_owned()), arg[eq + 1..].to_owned()),
+      _ => Bus::Address(None, arg.to_owned()),
+    }
+  }
+
+  /// Name of the directory of the bus when several buses are mounted.
+  pub fn name(&self) -> Option<&str> {
+    match *self {
+      Bus::System => Some("system"),
+      Bus::Session => Some("session"),
+      Bus::Starter => Some("starter"),
+      Bus::Address(ref name, _) => name.as_ref().map(|name| &**name),
+    }
+  }
+}
+
+/// Names of the bus directories, in order, `busN` for the N-th bus when it's an
+/// address given without a name.
+pub fn dir_names(buses: &[Bus]) -> Vec<String> {
+  buses.iter().enumerate().map(|(no, bus)| bus.name().map_or_else(|| format!("bus{}", no), |name| name.to_owned())).collect()
+}
+
+/// Connect to all buses, in order. Every bus gets a private connection of its own,
+/// so the session bus and any number of addresses can be mounted together.
+pub fn connect_all(buses: &[Bus]) -> Result<Vec<Channel>, dbus::Error> {
+  buses.iter().map(connect).collect()
+}
+
+fn connect(bus: &Bus) -> Result<Channel, dbus::Error> {
+  let address = match *bus {
+    Bus::System => return Channel::get_private(BusType::System),
+    Bus::Starter => return Channel::get_private(BusType::Starter),
+    Bus::Session => {
+      match session_address() {
+        Some(address) => address,
+        None => return Err(dbus::Error::new_custom(DBUS_BAD_ADDRESS_ERROR, "Unable to find the session bus address")),
+      }
+    }
+    Bus::Address(_, ref address) => address.clone(),
+  };
+
+  let mut conn = Channel::open_private(&address)?;
+  conn.register()?;
+  Ok(conn)
+}
+
+/// Find the session bus the way libdbus does: `DBUS_SESSION_BUS_ADDRESS` first,
+/// then a socket at `$XDG_RUNTIME_DIR/bus` owned by the current user.
+pub fn session_address() -> Option<String> {
+  if let Some(address) = env::var("DBUS_SESSION_BUS_ADDRESS").ok().and_then(|a| if a.is_empty() { None } else { Some(a) }) {
+    return Some(address);
+  }
+
+  let runtime_dir = match env::var("XDG_RUNTIME_DIR") {
+    Ok(dir) => dir,
+    Err(_) => return None,
+  };
+  let path = Path::new(&runtime_dir).join("bus");
+  match fs::metadata(&path) {
+    Ok(ref meta) if meta.file_type().is_socket() && meta.uid() == get_current_uid() => {
+      path.to_str().map(|path| format!("unix:path={}", escape(path)))
+    }
+    _ => None,
+  }
+}
+
+/// Escape a value for a D-Bus address, bytes outside of `[-0-9A-Za-z_/.\*]` become `%XX`.
+fn escape(value: &str) -> String {
+  let mut out = String::with_capacity(value.len());
+  for &b in value.as_bytes() {
+    match b {
+      b'-' | b'0'..=b'9' | b'A'..=b'Z' | b'a'..=b'z' | b'_' | b'/' | b'.' | b'\\' | b'*' => out.push(b as char),
+      b => out.push_str(&format!("%{:02x}", b)),
+    }
+  }
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{dir_names, escape, Bus};
+
+  fn address(name: Option<&str>, address: &str) -> Bus {
+    Bus::Address(name.map(|name| name.to_owned()), address.to_owned())
+  }
+
+  #[test]
+  fn addresses() {
+    let cases = [("unix:path=/x", address(None, "unix:path=/x")),
+                 ("x=unix:path=/x", address(Some("x"), "unix:path=/x")),
+                 ("unix:path=/x=y", address(None, "unix:path=/x=y")),
+                 ("x=unix:path=/x=y", address(Some("x"), "unix:path=/x=y")),
+                 ("tcp:host=h,port=1", address(None, "tcp:host=h,port=1")),
+                 ("unix:abstract=x;unix:path=/y", address(None, "unix:abstract=x;unix:path=/y")),
+                 ("=unix:path=/x", address(Some(""), "unix:path=/x")),
+                 ("system", address(None, "system"))];
+    for &(arg, ref bus) in &cases {
+      assert_eq!(Bus::from_address(arg), *bus, "{}", arg);
+    }
+  }
+
+  #[test]
+  fn directory_names() {
+    let buses = [Bus::System, address(None, "unix:path=/x"), address(Some("x"), "unix:path=/y"), Bus::Session];
+    assert_eq!(dir_names(&buses), vec!["system", "bus1", "x", "session"]);
+  }
+
+  #[test]
+  fn escaped_paths() {
+    assert_eq!(escape("/run/user/1000/bus"), "/run/user/1000/bus");
+    assert_eq!(escape("/tmp/a b,c=d"), "/tmp/a%20b%2cc%3dd");
+  }
+}
@@ -0,0 +1,234 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use dbus::Message;
+use dbus::arg::messageitem::MessageItem;
+use time::{Duration, SteadyTime};
+
+use node::NodeInfo;
+
+/// Seconds introspection data is kept unless told otherwise.
+pub const DEFAULT_CACHE_TTL: i64 = 10;
+
+/// Seconds the kernel keeps entries and attributes unless told otherwise.
+pub const DEFAULT_KERNEL_TTL: i64 = 10;
+
+/// Signals telling that introspection data, and the files made from it, have changed.
+pub static RULES: &[&str] = &["type='signal',sender='org.freedesktop.DBus',interface='org.freedesktop.DBus',\
+                               member='NameOwnerChanged'",
+                              "type='signal',interface='org.freedesktop.DBus.ObjectManager',member='InterfacesAdded'",
+                              "type='signal',interface='org.freedesktop.DBus.ObjectManager',member='InterfacesRemoved'"];
+
+/// Bus, destination and object path introspected.
+type Key = (usize, String, String);
+
+/// Parsed introspection data by bus, destination and object path.
+///
+/// Entries expire after a while, and are dropped early when their name changes
+/// owner or an object manager reports interfaces added to or removed from their
+/// object or one below it. A TTL of zero turns caching off.
+///
+/// The unique names owning the well-known names introspected are kept along, so
+/// that object managers only make the data of their own names stale.
+pub struct Cache {
+  ttl: Duration,
+  entries: RefCell<HashMap<Key, (SteadyTime, NodeInfo)>>,
+  owners: RefCell<HashMap<(usize, String), String>>,
+}
+
+impl Default for Cache {
+  fn default() -> Cache {
+    Cache::new(DEFAULT_CACHE_TTL)
+  }
+}
+
+impl Cache {
+  pub fn new(ttl_secs: i64) -> Cache {
+    Cache {
+      ttl: Duration::seconds(ttl_secs),
+      entries: RefCell::new(HashMap::new()),
+      owners: RefCell::new(HashMap::new()),
+    }
+  }
+
+  pub fn enabled(&self) -> bool {
+    self.ttl > Duration::zero()
+  }
+
+  pub fn get(&self, bus: usize, dest: &str, path: &str) -> Option<NodeInfo> {
+    let key = (bus, dest.to_owned(), path.to_owned());
+    let mut entries = self.entries.borrow_mut();
+    let expired = match entries.get(&key) {
+      Some(&(since, ref info)) if SteadyTime::now() - since < self.ttl => return Some(info.clone()),
+      Some(_) => true,
+      None => false,
+    };
+    if expired {
+      entries.remove(&key);
+    }
+    None
+  }
+
+  pub fn insert(&self, bus: usize, dest: &str, path: &str, info: &NodeInfo) {
+    if self.enabled() {
+      self.entries.borrow_mut().insert((bus, dest.to_owned(), path.to_owned()), (SteadyTime::now(), info.clone()));
+    }
+  }
+
+  /// The unique name owning a name, as far as it's known. A unique name owns itself.
+  pub fn owner(&self, bus: usize, name: &str) -> Option<String> {
+    if name.starts_with(':') {
+      return Some(name.to_owned());
+    }
+    self.owners.borrow().get(&(bus, name.to_owned())).cloned()
+  }
+
+  pub fn insert_owner(&self, bus: usize, name: &str, owner: &str) {
+    self.owners.borrow_mut().insert((bus, name.to_owned()), owner.to_owned());
+  }
+
+  /// Drop the introspection data a change makes stale, and follow names to their new owners.
+  pub fn forget(&self, bus: usize, change: &Change) {
+    if let Change::Owner(ref name, ref owner) = *change {
+      if owner.is_empty() {
+        self.owners.borrow_mut().remove(&(bus, name.clone()));
+      } else {
+        self.insert_owner(bus, name, owner);
+      }
+    }
+    self.entries.borrow_mut().retain(|&(b, ref dest, ref path), _| {
+      b != bus || !change.stales_info(dest, self.owner(bus, dest).as_deref(), path)
+    });
+  }
+}
+
+/// A change on the bus, as told by a signal on one of `RULES`.
+#[derive(Debug, Clone)]
+pub enum Change {
+  /// A name got a new owner or lost it, with the new owner, empty if none.
+  Owner(String, String),
+  /// Interfaces were added to or removed from an object, by the object manager
+  /// with the unique name.
+  Object(String, String),
+}
+
+impl Change {
+  pub fn from_signal(msg: &Message) -> Option<Change> {
+    let items = msg.get_items();
+    let arg = match items.first() {
+      Some(MessageItem::Str(name)) => name.clone(),
+      Some(MessageItem::ObjectPath(path)) => (**path).to_owned(),
+      _ => return None,
+    };
+
+    match (msg.interface().as_deref(), msg.member().as_deref()) {
+      (Some("org.freedesktop.DBus"), Some("NameOwnerChanged")) => {
+        match items.get(2) {
+          Some(MessageItem::Str(owner)) => Some(Change::Owner(arg, owner.clone())),
+          _ => None,
+        }
+      }
+      (Some("org.freedesktop.DBus.ObjectManager"), Some("InterfacesAdded")) |
+      (Some("org.freedesktop.DBus.ObjectManager"), Some("InterfacesRemoved")) => {
+        msg.sender().map(|sender| Change::Object((*sender).to_owned(), arg))
+      }
+      _ => None,
+    }
+  }
+
+  /// Whether introspection data of an object is stale, given the unique name owning
+  /// its destination. A new or gone object changes the children of the objects above
+  /// it too.
+  pub fn stales_info(&self, dest: &str, owner: Option<&str>, path: &str) -> bool {
+    match *self {
+      Change::Owner(ref name, _) => dest == name,
+      Change::Object(ref sender, ref object) => {
+        owned_by(owner, sender) && (path == "/" || path == object || object.starts_with(&format!("{}/", path)))
+      }
+    }
+  }
+
+  /// Whether a file standing for an object, or something in it, is stale. The
+  /// objects above stay what they were.
+  pub fn stales_node(&self, dest: &str, owner: Option<&str>, path: &str) -> bool {
+    match *self {
+      Change::Owner(ref name, _) => dest == name,
+      Change::Object(ref sender, ref object) => {
+        owned_by(owner, sender) && (object == "/" || path == object || path.starts_with(&format!("{}/", object)))
+      }
+    }
+  }
+}
+
+/// Whether a destination is owned by the sender of a signal. One whose owner isn't
+/// known may be, its data is rather dropped than kept stale.
+fn owned_by(owner: Option<&str>, sender: &str) -> bool {
+  owner.is_none_or(|owner| owner == sender)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Cache, Change};
+
+  fn object(sender: &str, path: &str) -> Change {
+    Change::Object(sender.to_owned(), path.to_owned())
+  }
+
+  #[test]
+  fn object_change_stales_its_info_and_the_objects_above() {
+    let change = object(":1.5", "/org/x/Obj");
+    for path in &["/", "/org", "/org/x", "/org/x/Obj"] {
+      assert!(change.stales_info("org.x", Some(":1.5"), path), "{}", path);
+    }
+    assert!(!change.stales_info("org.x", Some(":1.5"), "/org/x/Obj/Child"));
+    assert!(!change.stales_info("org.x", Some(":1.5"), "/org/xy"));
+    assert!(!change.stales_info("org.x", Some(":1.5"), "/org/x/Other"));
+  }
+
+  #[test]
+  fn object_change_stales_its_nodes_and_the_objects_below() {
+    let change = object(":1.5", "/org/x/Obj");
+    for path in &["/org/x/Obj", "/org/x/Obj/Child"] {
+      assert!(change.stales_node("org.x", Some(":1.5"), path), "{}", path);
+    }
+    for path in &["/", "/org", "/org/x", "/org/x/Objects"] {
+      assert!(!change.stales_node("org.x", Some(":1.5"), path), "{}", path);
+    }
+    assert!(object(":1.5", "/").stales_node("org.x", Some(":1.5"), "/org"));
+  }
+
+  #[test]
+  fn object_change_leaves_other_owners_alone() {
+    let change = object(":1.5", "/");
+    assert!(!change.stales_info("org.y", Some(":1.6"), "/"));
+    assert!(!change.stales_node("org.y", Some(":1.6"), "/org/y"));
+    assert!(!change.stales_info(":1.6", Some(":1.6"), "/"));
+    // Not knowing better, a name may be owned by the sender.
+    assert!(change.stales_info("org.y", None, "/"));
+  }
+
+  #[test]
+  fn owner_change_stales_everything_of_the_name() {
+    let change = Change::Owner("org.x".to_owned(), ":1.7".to_owned());
+    assert!(change.stales_info("org.x", Some(":1.5"), "/org/x"));
+    assert!(change.stales_node("org.x", None, "/"));
+    assert!(!change.stales_info("org.y", Some(":1.5"), "/org/x"));
+    assert!(!change.stales_node("org.xy", None, "/"));
+  }
+
+  #[test]
+  fn owners_follow_owner_changes() {
+    let cache = Cache::new(10);
+    assert_eq!(cache.owner(0, ":1.5"), Some(":1.5".to_owned()));
+    assert_eq!(cache.owner(0, "org.x"), None);
+
+    cache.insert_owner(0, "org.x", ":1.5");
+    assert_eq!(cache.owner(0, "org.x"), Some(":1.5".to_owned()));
+    assert_eq!(cache.owner(1, "org.x"), None);
+
+    cache.forget(0, &Change::Owner("org.x".to_owned(), ":1.7".to_owned()));
+    assert_eq!(cache.owner(0, "org.x"), Some(":1.7".to_owned()));
+    cache.forget(0, &Change::Owner("org.x".to_owned(), String::new()));
+    assert_eq!(cache.owner(0, "org.x"), None);
+  }
+}
@@ -0,0 +1,432 @@
+use std::path::Path;
+
+use fuser::MountOption;
+
+use getopts;
+use users::get_user_by_name;
+
+use bus::{self, Bus};
+use cache;
+use errno::Errnos;
+use filter::{Filter, Scope};
+use format::Format;
+use guard::Guard;
+use timeout::{self, Timeouts};
+
+/// Everything needed to mount, as given on the command line.
+pub struct Config {
+  pub mountpoint: String,
+  pub buses: Vec<Bus>,
+  pub format: Format,
+  pub foreground: bool,
+  pub read_only: bool,
+  pub filter: Filter,
+  pub guard: Guard,
+  pub timeouts: Timeouts,
+  pub errnos: Errnos,
+  /// Seconds introspection data is cached, zero for not at all.
+  pub cache_ttl: i64,
+  /// Seconds the kernel may cache entries and attributes.
+  pub ttl: i64,
+  /// User to serve the mount as, the bus is connected to with their credentials.
+  pub uid: Option<u32>,
+  /// Only check the options, as `mount -f` asks a helper to.
+  pub fake: bool,
+  /// Options handed over to FUSE as they are, like `allow_other` or `ro`.
+  pub fuse_options: Vec<String>,
+}
+
+/// Options mount(8) passes to every helper, they mean nothing to FUSE.
+static MOUNT_OPTIONS: &[&str] = &["defaults", "auto", "noauto", "user", "nouser", "users", "nofail", "_netdev"];
+
+impl Config {
+  pub fn new(mountpoint: String) -> Config {
+    Config {
+      mountpoint,
+      buses: Vec::new(),
+      format: Format::default(),
+      foreground: false,
+      read_only: false,
+      filter: Filter::default(),
+      guard: Guard::default(),
+      timeouts: Timeouts::default(),
+      errnos: Errnos::default(),
+      cache_ttl: cache::DEFAULT_CACHE_TTL,
+      ttl: cache::DEFAULT_KERNEL_TTL,
+      uid: None,
+      fake: false,
+      fuse_options: Vec::new(),
+    }
+  }
+
+  /// Take the options dbusfs understands out of a comma separated `-o` list
+  /// and keep the rest for FUSE.
+  pub fn mount_options(&mut self, opts: &str) -> Result<(), String> {
+    for opt in opts.split(',').filter(|opt| !opt.is_empty()) {
+      let (name, value) = match opt.find('=') {
+        Some(eq) => (&opt[..eq], Some(&opt[eq + 1..])),
+        None => (opt, None),
+      };
+
+      match (name, value) {
+        ("format", Some(format)) => self.format = parse_format(format)?,
+        ("bus", Some(bus)) => self.buses.push(parse_bus(bus)),
+        ("filter", Some(path)) => self.filter.load(Path::new(path))?,
+        ("guard", Some(pattern)) => self.guard.add(pattern)?,
+        ("allow_guarded", None) => self.guard.allow_all(),
+        ("timeout", Some(ms)) => self.timeouts.set_default(timeout::parse_ms(ms)?),
+        ("dest_timeout", Some(spec)) => self.timeouts.parse_dest(spec)?,
+        ("errno", Some(spec)) => self.errnos.parse(spec)?,
+        ("cache_ttl", Some(secs)) => self.cache_ttl = parse_secs(secs)?,
+        ("ttl", Some(secs)) => self.ttl = parse_secs(secs)?,
+        ("foreground", None) => self.foreground = true,
+        ("uid", Some(uid)) => self.uid = Some(parse_uid(uid)?),
+        // Both for FUSE and for us, the kernel only refuses writes, not reads that make calls.
+        ("ro", None) => {
+          self.read_only = true;
+          self.fuse_options.push(opt.to_owned());
+        }
+        (name, None) if MOUNT_OPTIONS.contains(&name) => (),
+        _ => self.fuse_options.push(opt.to_owned()),
+      }
+    }
+    Ok(())
+  }
+
+  /// Options for the FUSE mount, with `fsname` and `subtype` filled in unless given.
+  pub fn fuse_mount_options(&self) -> Vec<MountOption> {
+    let mut opts = self.fuse_options.iter().map(|opt| mount_option(opt)).collect::<Vec<_>>();
+    if !self.fuse_options.iter().any(|opt| opt.starts_with("fsname=")) {
+      opts.push(MountOption::FSName("dbusfs".to_owned()));
+    }
+    if !self.fuse_options.iter().any(|opt| opt.starts_with("subtype=")) {
+      opts.push(MountOption::Subtype("dbusfs".to_owned()));
+    }
+    opts
+  }
+}
+
+/// A FUSE option as fuser takes it, anything it has no variant for is passed on as it is.
+fn mount_option(opt: &str) -> MountOption {
+  match opt {
+    "allow_other" => MountOption::AllowOther,
+    "allow_root" => MountOption::AllowRoot,
+    "auto_unmount" => MountOption::AutoUnmount,
+    "default_permissions" => MountOption::DefaultPermissions,
+    "dev" => MountOption::Dev,
+    "nodev" => MountOption::NoDev,
+    "suid" => MountOption::Suid,
+    "nosuid" => MountOption::NoSuid,
+    "ro" => MountOption::RO,
+    "rw" => MountOption::RW,
+    "exec" => MountOption::Exec,
+    "noexec" => MountOption::NoExec,
+    "atime" => MountOption::Atime,
+    "noatime" => MountOption::NoAtime,
+    "dirsync" => MountOption::DirSync,
+    "sync" => MountOption::Sync,
+    "async" => MountOption::Async,
+    opt if opt.starts_with("fsname=") => MountOption::FSName(opt["fsname=".len()..].to_owned()),
+    opt if opt.starts_with("subtype=") => MountOption::Subtype(opt["subtype=".len()..].to_owned()),
+    opt => MountOption::CUSTOM(opt.to_owned()),
+  }
+}
+
+fn parse_format(format: &str) -> Result<Format, String> {
+  format.parse().map_err(|_| format!("unknown format `{}`, expected text, json, env or raw", format))
+}
+
+/// `bus=` takes `system`, `session`, `starter` or an address, which can't have
+/// commas here as `-o` lists are split on them.
+fn parse_bus(bus: &str) -> Bus {
+  match bus {
+    "system" => Bus::System,
+    "session" => Bus::Session,
+    "starter" => Bus::Starter,
+    address => Bus::from_address(address),
+  }
+}
+
+/// Every bus needs a directory of its own, `--system --address system=ADDRESS`
+/// would hide one behind the other.
+fn check_buses(buses: &[Bus]) -> Result<(), String> {
+  let names = bus::dir_names(buses);
+  for (no, name) in names.iter().enumerate() {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+      return Err(format!("bad bus name `{}`", name));
+    }
+    if names[..no].contains(name) {
+      return Err(format!("bus name `{}` given twice", name));
+    }
+  }
+  Ok(())
+}
+
+fn parse_secs(secs: &str) -> Result<i64, String> {
+  match secs.parse() {
+    Ok(secs) if secs >= 0 => Ok(secs),
+    _ => Err(format!("bad TTL `{}`, expected a number of seconds", secs)),
+  }
+}
+
+fn parse_uid(uid: &str) -> Result<u32, String> {
+  uid.parse()
+     .ok()
+     .or_else(|| get_user_by_name(uid).map(|user| user.uid))
+     .ok_or_else(|| format!("unknown user `{}`", uid))
+}
+
+/// Filter options, `--include-dest` and so on, with what they filter.
+static FILTER_SCOPES: &[(&str, Scope, &str)] = &[("dest", Scope::Dest, "bus names"),
+                                                 ("path", Scope::Path, "object paths"),
+                                                 ("iface", Scope::Iface, "interfaces"),
+                                                 ("member", Scope::Member, "methods, properties and signals")];
+
+fn options() -> getopts::Options {
+  let mut opts = getopts::Options::new();
+  opts.optflag("h", "help", "print this help and exit");
+  opts.optflag("f", "foreground", "stay in the foreground instead of running as a daemon");
+  opts.optflag("r", "read-only", "refuse property writes and method calls with EROFS, like -o ro");
+  opts.optmulti("o", "", "mount options, dbusfs ones and any FUSE option like allow_other, ro, default_permissions, fsname=NAME or auto_unmount", "OPTS");
+  opts.optflag("", "system", "mount the system bus, the default");
+  opts.optflag("", "session", "mount the session bus");
+  opts.optflag("", "starter", "mount the bus that started us");
+  opts.optmulti("", "address", "mount the bus at a D-Bus address, in a directory NAME if given", "[NAME=]ADDRESS");
+  for &(scope, _, what) in FILTER_SCOPES {
+    opts.optmulti("", &format!("include-{}", scope), &format!("only show {} matching a glob, or a regex as re:PATTERN", what), "PATTERN");
+    opts.optmulti("", &format!("exclude-{}", scope), &format!("hide {} matching a glob, or a regex as re:PATTERN", what), "PATTERN");
+  }
+  opts.optmulti("", "filter", "read include and exclude rules from a file", "FILE");
+  opts.optmulti("", "guard", "refuse calls of interface.Member matching a glob, or a regex as re:PATTERN", "PATTERN");
+  opts.optflag("", "no-default-guards", "don't guard the power and unit management methods of logind and systemd");
+  opts.optflag("", "allow-guarded", "allow calls of guarded methods");
+  opts.optopt("", "timeout", &format!("milliseconds to wait for replies, {} by default", timeout::DEFAULT_TIMEOUT_MS), "MS");
+  opts.optmulti("", "dest-timeout", "milliseconds to wait for replies from bus names matching a glob, or a regex as re:PATTERN", "NAME=MS");
+  opts.optmulti("", "errno", "report D-Bus errors named like a glob, or a regex as re:PATTERN, as an errno like EBUSY", "NAME=ERRNO");
+  opts.optopt("", "cache-ttl", &format!("seconds to keep introspection data, {} by default, 0 for not at all", cache::DEFAULT_CACHE_TTL), "SECS");
+  opts.optopt("", "ttl", &format!("seconds the kernel may cache entries and attributes, {} by default", cache::DEFAULT_KERNEL_TTL), "SECS");
+  opts.optopt("", "format", "format of files without a format suffix: text, json, env or raw", "FORMAT");
+  opts
+}
+
+/// Options of a mount(8) helper, `mount -t dbusfs` runs `mount.dbusfs SOURCE MOUNTPOINT -o OPTS`.
+fn helper_options() -> getopts::Options {
+  let mut opts = getopts::Options::new();
+  opts.optflag("h", "help", "print this help and exit");
+  opts.optmulti("o", "", "mount options: bus=BUS, uid=USER, filter=FILE, guard=PATTERN, allow_guarded, timeout=MS, dest_timeout=NAME=MS, errno=NAME=ERRNO, cache_ttl=SECS, ttl=SECS, format=FORMAT and any FUSE option", "OPTS");
+  opts.optflag("f", "", "fake mount, only check the options");
+  opts.optflag("n", "", "don't write to /etc/mtab, ignored");
+  opts.optflag("s", "", "tolerate sloppy options, ignored");
+  opts.optflag("v", "", "verbose, ignored");
+  opts
+}
+
+pub fn usage(program: &str) -> String {
+  options().usage(&format!("Usage: {} [options] MOUNTPOINT", program))
+}
+
+pub fn helper_usage(program: &str) -> String {
+  helper_options().usage(&format!("Usage: {} SOURCE MOUNTPOINT [-o OPTS]\n\nSOURCE is a bus unless bus= is given: system, \
+                                   session, starter or an address.",
+                                  program))
+}
+
+/// Parse the command line, `Ok(None)` means help was asked for.
+pub fn parse(args: &[String]) -> Result<Option<Config>, String> {
+  let matches = options().parse(&args[1..]).map_err(|e| e.to_string())?;
+  if matches.opt_present("help") {
+    return Ok(None);
+  }
+
+  let mountpoint = match matches.free.len() {
+    0 => return Err("missing mountpoint".to_owned()),
+    1 => matches.free[0].clone(),
+    _ => return Err(format!("unexpected argument `{}`", matches.free[1])),
+  };
+
+  let mut config = Config::new(mountpoint);
+  config.foreground = matches.opt_present("foreground");
+  config.read_only = matches.opt_present("read-only");
+
+  if matches.opt_present("system") {
+    config.buses.push(Bus::System);
+  }
+  if matches.opt_present("session") {
+    config.buses.push(Bus::Session);
+  }
+  if matches.opt_present("starter") {
+    config.buses.push(Bus::Starter);
+  }
+  for address in matches.opt_strs("address") {
+    config.buses.push(Bus::from_address(&address));
+  }
+  if let Some(format) = matches.opt_str("format") {
+    config.format = parse_format(&format)?;
+  }
+  for &(name, scope, _) in FILTER_SCOPES {
+    for pattern in matches.opt_strs(&format!("include-{}", name)) {
+      config.filter.include(scope, &pattern)?;
+    }
+    for pattern in matches.opt_strs(&format!("exclude-{}", name)) {
+      config.filter.exclude(scope, &pattern)?;
+    }
+  }
+  for path in matches.opt_strs("filter") {
+    config.filter.load(Path::new(&path))?;
+  }
+  if matches.opt_present("no-default-guards") {
+    config.guard.clear();
+  }
+  for pattern in matches.opt_strs("guard") {
+    config.guard.add(&pattern)?;
+  }
+  if matches.opt_present("allow-guarded") {
+    config.guard.allow_all();
+  }
+  if let Some(ms) = matches.opt_str("timeout") {
+    config.timeouts.set_default(timeout::parse_ms(&ms)?);
+  }
+  for spec in matches.opt_strs("dest-timeout") {
+    config.timeouts.parse_dest(&spec)?;
+  }
+  for spec in matches.opt_strs("errno") {
+    config.errnos.parse(&spec)?;
+  }
+  if let Some(secs) = matches.opt_str("cache-ttl") {
+    config.cache_ttl = parse_secs(&secs)?;
+  }
+  if let Some(secs) = matches.opt_str("ttl") {
+    config.ttl = parse_secs(&secs)?;
+  }
+  for opts in matches.opt_strs("o") {
+    config.mount_options(&opts)?;
+  }
+
+  if config.buses.is_empty() {
+    config.buses.push(Bus::default());
+  }
+  check_buses(&config.buses)?;
+  Ok(Some(config))
+}
+
+/// Parse the arguments of a mount(8) helper. The source names the bus, unless
+/// `bus=` is given, and the file system in the mount table.
+pub fn parse_helper(args: &[String]) -> Result<Option<Config>, String> {
+  let matches = helper_options().parse(&args[1..]).map_err(|e| e.to_string())?;
+  if matches.opt_present("help") {
+    return Ok(None);
+  }
+
+  let (source, mountpoint) = match matches.free.len() {
+    0 | 1 => return Err("missing source or mountpoint".to_owned()),
+    2 => (matches.free[0].clone(), matches.free[1].clone()),
+    _ => return Err(format!("unexpected argument `{}`", matches.free[2])),
+  };
+
+  let mut config = Config::new(mountpoint);
+  config.fake = matches.opt_present("f");
+  for opts in matches.opt_strs("o") {
+    config.mount_options(&opts)?;
+  }
+
+  if config.buses.is_empty() {
+    match &*source {
+      "system" | "session" | "starter" => config.buses.push(parse_bus(&source)),
+      address if address.contains(':') => config.buses.push(parse_bus(address)),
+      _ => config.buses.push(Bus::default()),
+    }
+  }
+  check_buses(&config.buses)?;
+  if !config.fuse_options.iter().any(|opt| opt.starts_with("fsname=")) {
+    config.fuse_options.push(format!("fsname={}", source.replace(',', "_")));
+  }
+  Ok(Some(config))
+}
+
+#[cfg(test)]
+mod tests {
+  use bus::Bus;
+  use format::Format;
+
+  use super::{parse, parse_helper};
+
+  fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| (*arg).to_owned()).collect()
+  }
+
+  fn helper(opts: &str) -> Result<super::Config, String> {
+    parse_helper(&args(&["mount.dbusfs", "system", "/mnt", "-o", opts])).map(|config| config.unwrap())
+  }
+
+  #[test]
+  fn mount_options() {
+    let cases: &[(&str, &[&str], bool)] = &[("", &[], false),
+                                            ("ro", &["ro"], true),
+                                            ("allow_other", &["allow_other"], false),
+                                            ("ro,allow_other", &["ro", "allow_other"], true),
+                                            (",,allow_other,", &["allow_other"], false),
+                                            ("defaults,noauto,user,_netdev", &[], false),
+                                            ("timeout=50,default_permissions,ttl=0", &["default_permissions"], false),
+                                            ("max_read=4096,fsname=bus", &["max_read=4096", "fsname=bus"], false),
+                                            ("x-systemd.automount,cache_ttl=0", &["x-systemd.automount"], false)];
+    for &(opts, fuse, read_only) in cases {
+      let config = parse(&args(&["dbusfs", "-o", opts, "/mnt"])).unwrap().unwrap();
+      assert_eq!(config.fuse_options, fuse, "{}", opts);
+      assert_eq!(config.read_only, read_only, "{}", opts);
+    }
+  }
+
+  #[test]
+  fn mount_options_are_parsed() {
+    let config = helper("format=json,ttl=3,cache_ttl=0,foreground,allow_guarded").unwrap();
+    assert_eq!(config.format, Format::Json);
+    assert_eq!(config.ttl, 3);
+    assert_eq!(config.cache_ttl, 0);
+    assert!(config.foreground);
+    assert_eq!(config.fuse_options, vec!["fsname=system".to_owned()]);
+
+    for opts in &["format=xml", "ttl=-1", "cache_ttl=soon", "timeout=", "guard=re:(", "errno=x=ENOPE", "uid=no such user"] {
+      assert!(helper(opts).is_err(), "{}", opts);
+    }
+  }
+
+  #[test]
+  fn helper_buses() {
+    let cases: &[(&[&str], &[Bus])] = &[(&["system", "/mnt"], &[Bus::System]),
+                                        (&["session", "/mnt"], &[Bus::Session]),
+                                        (&["dbusfs", "/mnt"], &[Bus::System]),
+                                        (&["unix:path=/x", "/mnt"], &[Bus::Address(None, "unix:path=/x".to_owned())]),
+                                        (&["dbusfs", "/mnt", "-o", "bus=session,bus=starter"], &[Bus::Session, Bus::Starter]),
+                                        (&["dbusfs", "/mnt", "-o", "bus=x=unix:path=/x"],
+                                         &[Bus::Address(Some("x".to_owned()), "unix:path=/x".to_owned())])];
+    for &(given, buses) in cases {
+      let mut all = vec!["mount.dbusfs"];
+      all.extend_from_slice(given);
+      assert_eq!(parse_helper(&args(&all)).unwrap().unwrap().buses, buses, "{:?}", given);
+    }
+  }
+
+  #[test]
+  fn bus_names_must_be_distinct_directories() {
+    let good: &[&[&str]] = &[&["dbusfs", "/mnt"],
+                             &["dbusfs", "--system", "--session", "/mnt"],
+                             &["dbusfs", "--system", "--address", "other=unix:path=/x", "/mnt"],
+                             &["dbusfs", "--address", "unix:path=/x", "--address", "unix:path=/y", "/mnt"]];
+    for given in good {
+      assert!(parse(&args(given)).is_ok(), "{:?}", given);
+    }
+
+    let bad: &[&[&str]] = &[&["dbusfs", "--system", "--address", "system=unix:path=/x", "/mnt"],
+                            &["dbusfs", "--address", "x=unix:path=/x", "--address", "x=unix:path=/y", "/mnt"],
+                            &["dbusfs", "--address", "unix:path=/x", "--address", "bus0=unix:path=/y", "/mnt"],
+                            &["dbusfs", "--address", "=unix:path=/x", "/mnt"],
+                            &["dbusfs", "--address", "a/b=unix:path=/x", "/mnt"],
+                            &["dbusfs", "--address", "..=unix:path=/x", "/mnt"],
+                            &["dbusfs", "--session", "-o", "bus=session", "/mnt"]];
+    for given in bad {
+      assert!(parse(&args(given)).is_err(), "{:?}", given);
+    }
+    assert!(helper("bus=session,bus=session").is_err());
+    assert!(helper("bus==unix:path=/x").is_err());
+  }
+}
@@ -0,0 +1,214 @@
+use std::env;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::io::FromRawFd;
+use std::path::PathBuf;
+use std::process::{self, Command};
+use std::ptr;
+use std::thread;
+
+use libc;
+
+/// Exit codes, as in sysexits.h.
+pub const EX_USAGE: i32 = 64;
+pub const EX_UNAVAILABLE: i32 = 69;
+pub const EX_SOFTWARE: i32 = 70;
+pub const EX_OSERR: i32 = 71;
+pub const EX_NOPERM: i32 = 77;
+
+// Missing from libc 0.2, SOCK_CLOEXEC is O_CLOEXEC on Linux.
+const SOCK_CLOEXEC: libc::c_int = libc::O_CLOEXEC;
+
+extern "C" {
+  fn sigwait(set: *const libc::sigset_t, signal: *mut libc::c_int) -> libc::c_int;
+}
+
+/// Link to whoever waits for the mount to come up: the parent process left in the
+/// foreground by `daemonize`, and systemd with `Type=notify`.
+#[derive(Default)]
+pub struct Daemon {
+  pipe: Option<File>,
+}
+
+impl Daemon {
+  /// Report the mount as live, once the kernel has sent its first request.
+  pub fn ready(&mut self) {
+    let _ = notify("READY=1");
+
+    if let Some(mut pipe) = self.pipe.take() {
+      let _ = pipe.write_all(&[0]);
+      detach_stdio();
+    }
+  }
+}
+
+/// Fork into the background. The parent stays until the mount is live and then exits
+/// successfully, or exits with the status of the child if it fails to get there, so
+/// that startup errors are still seen on its terminal.
+pub fn daemonize() -> io::Result<Daemon> {
+  let mut fds = [0; 2];
+  unsafe {
+    if libc::pipe(fds.as_mut_ptr()) == -1 {
+      return Err(io::Error::last_os_error());
+    }
+
+    match libc::fork() {
+      -1 => return Err(io::Error::last_os_error()),
+      0 => {
+        libc::close(fds[0]);
+      }
+      child => {
+        libc::close(fds[1]);
+        let mut pipe = File::from_raw_fd(fds[0]);
+        let mut status = [0; 1];
+        if let Ok(1) = pipe.read(&mut status) {
+          process::exit(status[0] as i32);
+        }
+
+        let mut status = 0;
+        if libc::waitpid(child, &mut status, 0) == child && libc::WIFEXITED(status) {
+          process::exit(libc::WEXITSTATUS(status));
+        }
+        process::exit(EX_SOFTWARE);
+      }
+    }
+
+    if libc::setsid() == -1 {
+      return Err(io::Error::last_os_error());
+    }
+    env::set_current_dir("/")?;
+
+    Ok(Daemon { pipe: Some(File::from_raw_fd(fds[1])) })
+  }
+}
+
+fn detach_stdio() {
+  unsafe {
+    let null = libc::open(b"/dev/null\0".as_ptr() as *const libc::c_char, libc::O_RDWR);
+    if null != -1 {
+      for fd in 0..3 {
+        libc::dup2(null, fd);
+      }
+      if null > 2 {
+        libc::close(null);
+      }
+    }
+  }
+}
+
+/// Send a state to systemd, see sd_notify(3). Does nothing without `$NOTIFY_SOCKET`.
+pub fn notify(state: &str) -> io::Result<()> {
+  let socket = match env::var("NOTIFY_SOCKET") {
+    Ok(socket) => socket,
+    Err(_) => return Ok(()),
+  };
+
+  unsafe {
+    let mut addr: libc::sockaddr_un = mem::zeroed();
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    if socket.is_empty() || socket.len() >= addr.sun_path.len() {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, "bad NOTIFY_SOCKET"));
+    }
+    for (dst, &src) in addr.sun_path.iter_mut().zip(socket.as_bytes()) {
+      *dst = src as libc::c_char;
+    }
+    // An abstract socket, its name starts with a zero byte.
+    if socket.starts_with('@') {
+      addr.sun_path[0] = 0;
+    }
+
+    let fd = libc::socket(libc::AF_UNIX, libc::SOCK_DGRAM | SOCK_CLOEXEC, 0);
+    if fd == -1 {
+      return Err(io::Error::last_os_error());
+    }
+    let len = mem::size_of::<libc::sa_family_t>() + socket.len();
+    let sent = libc::sendto(fd,
+                            state.as_ptr() as *const libc::c_void,
+                            state.len(),
+                            0,
+                            &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+                            len as libc::socklen_t);
+    let result = if sent == -1 {
+      Err(io::Error::last_os_error())
+    } else {
+      Ok(())
+    };
+    libc::close(fd);
+    result
+  }
+}
+
+/// Whether a thread has a signal pending that it neither blocks nor ignores. Gone
+/// threads count as interrupted too.
+pub fn interrupted(tid: u32) -> bool {
+  let mut status = String::new();
+  if File::open(format!("/proc/{}/status", tid)).and_then(|mut file| file.read_to_string(&mut status)).is_err() {
+    return true;
+  }
+
+  let mask = |field: &str| {
+    status.lines()
+          .find(|line| line.starts_with(field))
+          .and_then(|line| u64::from_str_radix(line[field.len()..].trim(), 16).ok())
+          .unwrap_or(0)
+  };
+  (mask("SigPnd:") | mask("ShdPnd:")) & !mask("SigBlk:") & !mask("SigIgn:") != 0
+}
+
+/// Unmount on SIGTERM and SIGINT and exit, instead of dying and leaving a dead mount
+/// behind. A lazy unmount keeps the session going while files are open in the mount,
+/// exiting ends it: they get ENOTCONN from then on. Failing to unmount exits all the
+/// same, with `EX_OSERR`, as asked to.
+///
+/// Has to be called before any other thread is started, they inherit the signal mask.
+pub fn unmount_on_signals(mountpoint: PathBuf) {
+  unsafe {
+    let mut set: libc::sigset_t = mem::zeroed();
+    libc::sigemptyset(&mut set);
+    libc::sigaddset(&mut set, libc::SIGTERM);
+    libc::sigaddset(&mut set, libc::SIGINT);
+    libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut());
+
+    thread::spawn(move || {
+      loop {
+        let mut signal = 0;
+        if sigwait(&set, &mut signal) != 0 {
+          continue;
+        }
+        match unmount(&mountpoint) {
+          Ok(()) => process::exit(0),
+          Err(err) => {
+            let _ = writeln!(io::stderr(), "dbusfs: can't unmount {}: {}", mountpoint.display(), err);
+            process::exit(EX_OSERR);
+          }
+        }
+      }
+    });
+  }
+}
+
+/// Unmount lazily, a file left open in the mount would make a plain unmount fail with EBUSY.
+fn unmount(mountpoint: &PathBuf) -> io::Result<()> {
+  // Only root may unmount directly, everybody else goes through fusermount like the mount did.
+  if unsafe { libc::geteuid() } == 0 {
+    let path = CString::new(mountpoint.to_string_lossy().into_owned())
+                 .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "bad mountpoint"))?;
+    if unsafe { libc::umount2(path.as_ptr(), libc::MNT_DETACH) } == 0 {
+      return Ok(());
+    }
+    return Err(io::Error::last_os_error());
+  }
+
+  // fuser mounts with fusermount3 when it's there, like libfuse 3.
+  let mut last = io::Error::new(io::ErrorKind::NotFound, "no fusermount");
+  for helper in &["fusermount3", "fusermount"] {
+    match Command::new(helper).arg("-u").arg("-z").arg(mountpoint).status() {
+      Ok(ref status) if status.success() => return Ok(()),
+      Ok(_) => return Err(io::Error::other(format!("{} -u -z failed", helper))),
+      Err(err) => last = err,
+    }
+  }
+  Err(last)
+}
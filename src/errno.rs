@@ -0,0 +1,113 @@
+use libc::{c_int, EACCES, EADDRINUSE, EAGAIN, EBADMSG, EBUSY, ECANCELED, ECONNREFUSED, EEXIST, EINVAL, EIO, EMSGSIZE, ENETUNREACH,
+           ENODEV, ENOENT, ENOMEM, ENOSPC, ENOSYS, ENOTCONN, ENOTEMPTY, ENXIO, EOPNOTSUPP, EPERM, EPROTO, EROFS, ETIMEDOUT};
+
+use filter::Pattern;
+
+static STANDARD_PREFIX: &str = "org.freedesktop.DBus.Error.";
+
+/// Errnos of the standard errors, named without `org.freedesktop.DBus.Error.`.
+static STANDARD_ERRORS: &[(&str, c_int)] = &[("AccessDenied", EACCES),
+                                             ("AuthFailed", EACCES),
+                                             ("InteractiveAuthorizationRequired", EACCES),
+                                             ("PropertyReadOnly", EACCES),
+                                             ("ServiceUnknown", ENOENT),
+                                             ("NameHasNoOwner", ENOENT),
+                                             ("UnknownObject", ENOENT),
+                                             ("UnknownProperty", ENOENT),
+                                             ("FileNotFound", ENOENT),
+                                             ("UnknownMethod", ENOSYS),
+                                             ("UnknownInterface", ENOSYS),
+                                             ("InvalidArgs", EINVAL),
+                                             ("InvalidSignature", EINVAL),
+                                             ("MatchRuleInvalid", EINVAL),
+                                             ("NotSupported", EOPNOTSUPP),
+                                             ("NoReply", ETIMEDOUT),
+                                             ("Timeout", ETIMEDOUT),
+                                             ("TimedOut", ETIMEDOUT),
+                                             ("LimitsExceeded", EAGAIN),
+                                             ("NoMemory", ENOMEM),
+                                             ("FileExists", EEXIST),
+                                             ("ObjectPathInUse", EEXIST),
+                                             ("AddressInUse", EADDRINUSE),
+                                             ("NoServer", ECONNREFUSED),
+                                             ("NoNetwork", ENETUNREACH),
+                                             ("Disconnected", ENOTCONN)];
+
+/// Errnos that can be given by name.
+static ERRNO_NAMES: &[(&str, c_int)] = &[("EPERM", EPERM),
+                                         ("ENOENT", ENOENT),
+                                         ("EIO", EIO),
+                                         ("ENXIO", ENXIO),
+                                         ("EAGAIN", EAGAIN),
+                                         ("ENOMEM", ENOMEM),
+                                         ("EACCES", EACCES),
+                                         ("EBUSY", EBUSY),
+                                         ("EEXIST", EEXIST),
+                                         ("ENODEV", ENODEV),
+                                         ("EINVAL", EINVAL),
+                                         ("ENOSPC", ENOSPC),
+                                         ("EROFS", EROFS),
+                                         ("ENOSYS", ENOSYS),
+                                         ("ENOTEMPTY", ENOTEMPTY),
+                                         ("EBADMSG", EBADMSG),
+                                         ("EPROTO", EPROTO),
+                                         ("EMSGSIZE", EMSGSIZE),
+                                         ("ENOTSUP", EOPNOTSUPP),
+                                         ("EOPNOTSUPP", EOPNOTSUPP),
+                                         ("EADDRINUSE", EADDRINUSE),
+                                         ("ENETUNREACH", ENETUNREACH),
+                                         ("ENOTCONN", ENOTCONN),
+                                         ("ETIMEDOUT", ETIMEDOUT),
+                                         ("ECONNREFUSED", ECONNREFUSED),
+                                         ("ECANCELED", ECANCELED)];
+
+/// Which errno a D-Bus error is reported as.
+///
+/// Custom mappings come first, the last matching one wins, then the standard
+/// errors. Anything else is EIO.
+#[derive(Default)]
+pub struct Errnos {
+  custom: Vec<(Pattern, c_int)>,
+}
+
+impl Errnos {
+  /// Report errors with names matching a glob, or a regex as `re:PATTERN`, as `errno`.
+  pub fn add(&mut self, pattern: &str, errno: c_int) -> Result<(), String> {
+    self.custom.push((Pattern::new(pattern)?, errno));
+    Ok(())
+  }
+
+  /// Add a mapping from `NAME=ERRNO`.
+  pub fn parse(&mut self, spec: &str) -> Result<(), String> {
+    match spec.rfind('=') {
+      Some(eq) => self.add(&spec[..eq], parse_errno(&spec[eq + 1..])?),
+      None => Err(format!("expected NAME=ERRNO, got `{}`", spec)),
+    }
+  }
+
+  pub fn errno(&self, name: Option<&str>) -> c_int {
+    let name = match name {
+      Some(name) => name,
+      None => return EIO,
+    };
+
+    if let Some(&(_, errno)) = self.custom.iter().rev().find(|&(p, _)| p.matches(name)) {
+      return errno;
+    }
+    if let Some(short) = name.strip_prefix(STANDARD_PREFIX) {
+      if let Some(&(_, errno)) = STANDARD_ERRORS.iter().find(|&&(n, _)| n == short) {
+        return errno;
+      }
+    }
+    EIO
+  }
+}
+
+/// An errno by name, like `EBUSY`, or by number.
+pub fn parse_errno(errno: &str) -> Result<c_int, String> {
+  ERRNO_NAMES.iter()
+             .find(|&&(name, _)| name == errno)
+             .map(|&(_, no)| no)
+             .or_else(|| errno.parse().ok().and_then(|no| if no > 0 { Some(no) } else { None }))
+             .ok_or_else(|| format!("unknown errno `{}`, expected a name like EBUSY or a positive number", errno))
+}
@@ -0,0 +1,225 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use regex::Regex;
+
+/// What a rule applies to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Scope {
+  Dest,
+  Path,
+  Iface,
+  Member,
+}
+
+impl Scope {
+  fn from_str(s: &str) -> Option<Scope> {
+    match s {
+      "dest" => Some(Scope::Dest),
+      "path" => Some(Scope::Path),
+      "iface" => Some(Scope::Iface),
+      "member" => Some(Scope::Member),
+      _ => None,
+    }
+  }
+}
+
+/// A glob with `*` and `?`, or a regex when written as `re:PATTERN`. Both have to
+/// match the whole name.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+  Glob(String),
+  Regex(Regex),
+}
+
+impl Pattern {
+  pub fn new(pattern: &str) -> Result<Pattern, String> {
+    if let Some(re) = pattern.strip_prefix("re:") {
+      Regex::new(&format!("^(?:{})$", re))
+        .map(Pattern::Regex)
+        .map_err(|e| format!("bad regex `{}`: {}", re, e))
+    } else {
+      Ok(Pattern::Glob(pattern.to_owned()))
+    }
+  }
+
+  pub fn matches(&self, name: &str) -> bool {
+    match *self {
+      Pattern::Glob(ref glob) => glob_match(glob.as_bytes(), name.as_bytes()),
+      Pattern::Regex(ref re) => re.is_match(name),
+    }
+  }
+
+  /// Whether `path` is an ancestor of the paths a glob can match, judging by the
+  /// part before its first wildcard. A regex can't tell.
+  fn leads_to(&self, path: &str) -> bool {
+    match *self {
+      Pattern::Glob(ref glob) => {
+        let fixed = glob.split(['*', '?']).next().unwrap_or("");
+        path == "/" || fixed.starts_with(&format!("{}/", path))
+      }
+      Pattern::Regex(_) => false,
+    }
+  }
+}
+
+fn glob_match(glob: &[u8], name: &[u8]) -> bool {
+  match glob.first() {
+    None => name.is_empty(),
+    Some(&b'*') => (0..name.len() + 1).any(|skip| glob_match(&glob[1..], &name[skip..])),
+    Some(&b'?') => !name.is_empty() && glob_match(&glob[1..], &name[1..]),
+    Some(c) => name.first() == Some(c) && glob_match(&glob[1..], &name[1..]),
+  }
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+  scope: Scope,
+  include: bool,
+  pattern: Pattern,
+}
+
+/// Include and exclude rules for what the mount shows.
+///
+/// A name is shown unless it matches an exclude rule of its scope, and, if the
+/// scope has include rules, unless it matches none of them. Object paths leading
+/// to an included one are shown as well, otherwise it couldn't be reached.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+  rules: Vec<Rule>,
+}
+
+impl Filter {
+  pub fn include(&mut self, scope: Scope, pattern: &str) -> Result<(), String> {
+    self.add(scope, true, pattern)
+  }
+
+  pub fn exclude(&mut self, scope: Scope, pattern: &str) -> Result<(), String> {
+    self.add(scope, false, pattern)
+  }
+
+  fn add(&mut self, scope: Scope, include: bool, pattern: &str) -> Result<(), String> {
+    let pattern = Pattern::new(pattern)?;
+    self.rules.push(Rule {
+      scope,
+      include,
+      pattern,
+    });
+    Ok(())
+  }
+
+  /// Read rules from a file, one `include|exclude dest|path|iface|member PATTERN`
+  /// per line. Empty lines and lines starting with `#` are skipped.
+  pub fn load(&mut self, path: &Path) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    for (no, line) in BufReader::new(file).lines().enumerate() {
+      let line = line.map_err(|e: io::Error| format!("can't read {}: {}", path.display(), e))?;
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      let words = line.split_whitespace().collect::<Vec<_>>();
+      match (words.first().cloned(), words.get(1).and_then(|w| Scope::from_str(w)), words.get(2)) {
+        (Some("include"), Some(scope), Some(pattern)) if words.len() == 3 => self.include(scope, pattern),
+        (Some("exclude"), Some(scope), Some(pattern)) if words.len() == 3 => self.exclude(scope, pattern),
+        _ => Err(format!("{}:{}: expected `include|exclude dest|path|iface|member PATTERN`", path.display(), no + 1)),
+      }?;
+    }
+    Ok(())
+  }
+
+  pub fn allows(&self, scope: Scope, name: &str) -> bool {
+    let mut included = None;
+    for rule in self.rules.iter().filter(|r| r.scope == scope) {
+      if rule.include {
+        let matched = rule.pattern.matches(name) || (scope == Scope::Path && rule.pattern.leads_to(name));
+        included = Some(included.unwrap_or(false) || matched);
+      } else if rule.pattern.matches(name) {
+        return false;
+      }
+    }
+    included.unwrap_or(true)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{glob_match, Filter, Pattern, Scope};
+
+  fn glob(glob: &str, name: &str) -> bool {
+    glob_match(glob.as_bytes(), name.as_bytes())
+  }
+
+  #[test]
+  fn globs() {
+    assert!(glob("", ""));
+    assert!(!glob("", "a"));
+    assert!(glob("*", ""));
+    assert!(glob("*", "org.x"));
+    assert!(glob("**", "org.x"));
+    assert!(glob("org.*", "org."));
+    assert!(glob("org.*.Obj", "org.x.y.Obj"));
+    assert!(!glob("org.*.Obj", "org.x.Objects"));
+    assert!(glob("*Obj", "ObjObj"));
+    assert!(!glob("?", ""));
+    assert!(glob("?", "x"));
+    assert!(!glob("?", "xy"));
+    assert!(glob("org.?", "org.x"));
+    assert!(glob("?*", "x"));
+    assert!(!glob("*?", ""));
+    assert!(!glob("org.x", "org.xy"));
+  }
+
+  #[test]
+  fn globs_lead_to_their_ancestors() {
+    let pattern = Pattern::new("/org/x/*").unwrap();
+    for path in &["/", "/org", "/org/x"] {
+      assert!(pattern.leads_to(path), "{}", path);
+    }
+    for path in &["/o", "/org/xy", "/org/x/Obj", "/com"] {
+      assert!(!pattern.leads_to(path), "{}", path);
+    }
+    assert!(!Pattern::new("/org/x").unwrap().leads_to("/org/x"));
+    assert!(Pattern::new("/org/x").unwrap().leads_to("/org"));
+    assert!(!Pattern::new("/org/x?/Obj").unwrap().leads_to("/org/x"));
+    assert!(!Pattern::new("re:/org/x/.*").unwrap().leads_to("/org"));
+  }
+
+  #[test]
+  fn included_paths_are_reachable() {
+    let mut filter = Filter::default();
+    filter.include(Scope::Path, "/org/x/*").unwrap();
+    for path in &["/", "/org", "/org/x", "/org/x/Obj"] {
+      assert!(filter.allows(Scope::Path, path), "{}", path);
+    }
+    assert!(!filter.allows(Scope::Path, "/org/y"));
+    assert!(!filter.allows(Scope::Path, "/com"));
+  }
+
+  #[test]
+  fn excludes_override_includes() {
+    let mut filter = Filter::default();
+    filter.include(Scope::Dest, "org.freedesktop.*").unwrap();
+    filter.exclude(Scope::Dest, "org.freedesktop.DBus").unwrap();
+    assert!(filter.allows(Scope::Dest, "org.freedesktop.login1"));
+    assert!(!filter.allows(Scope::Dest, "org.freedesktop.DBus"));
+    assert!(!filter.allows(Scope::Dest, "com.example"));
+
+    // Also when the exclude comes first, and for the paths leading to an include.
+    let mut filter = Filter::default();
+    filter.exclude(Scope::Path, "/org").unwrap();
+    filter.include(Scope::Path, "/org/x").unwrap();
+    assert!(!filter.allows(Scope::Path, "/org"));
+    assert!(filter.allows(Scope::Path, "/org/x"));
+  }
+
+  #[test]
+  fn rules_keep_to_their_scope() {
+    let mut filter = Filter::default();
+    filter.exclude(Scope::Member, "Get*").unwrap();
+    assert!(!filter.allows(Scope::Member, "GetAll"));
+    assert!(filter.allows(Scope::Iface, "GetAll"));
+  }
+}
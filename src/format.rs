@@ -0,0 +1,342 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use dbus::arg::messageitem::MessageItem;
+use value;
+
+/// How values are shown when property, method and signal files are read.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Format {
+  /// Text form from `value`, the only one that can be written back.
+  #[default]
+  Text,
+  Json,
+  /// `NAME='value'` lines that can be `source`d by a shell.
+  Env,
+  /// D-Bus wire marshalling of the message body, little endian.
+  Raw,
+}
+
+impl FromStr for Format {
+  type Err = ();
+  fn from_str(s: &str) -> Result<Format, ()> {
+    match s {
+      "text" => Ok(Format::Text),
+      "json" => Ok(Format::Json),
+      "env" => Ok(Format::Env),
+      "raw" => Ok(Format::Raw),
+      _ => Err(()),
+    }
+  }
+}
+
+impl Format {
+  /// Split a format suffix off a file name: `Prop.json` is `Prop` in JSON.
+  pub fn split_suffix(name: &str) -> (&str, Option<Format>) {
+    match name.rfind('.') {
+      Some(pos) => {
+        match name[pos + 1..].parse() {
+          Ok(format) => (&name[..pos], Some(format)),
+          Err(_) => (name, None),
+        }
+      }
+      None => (name, None),
+    }
+  }
+
+  /// Render a single named value, like a property.
+  pub fn value(&self, name: &str, item: &MessageItem) -> Vec<u8> {
+    match *self {
+      Format::Text => value::format(item).into_bytes(),
+      Format::Json => line(json(item)),
+      Format::Env => line(env(name, item)),
+      Format::Raw => marshal(&[item]),
+    }
+  }
+
+  /// Render the values of a method reply, named after the output arguments.
+  #[cfg_attr(feature = "readonly", allow(dead_code))]
+  pub fn reply(&self, names: &[&str], items: &[MessageItem]) -> Vec<u8> {
+    match *self {
+      Format::Text => value::format_args(items).into_bytes(),
+      Format::Json if items.len() == 1 => line(json(&items[0])),
+      Format::Json => line(json_list(items.iter())),
+      Format::Env => items.iter().enumerate().map(|(no, item)| line(env(&arg_name(names, no), item))).collect::<Vec<_>>().concat(),
+      Format::Raw => marshal(&items.iter().collect::<Vec<_>>()),
+    }
+  }
+
+  /// Render a signal as a single line, named after the signal arguments.
+  pub fn signal(&self, names: &[&str], items: &[MessageItem]) -> Vec<u8> {
+    match *self {
+      Format::Text => value::format_line(items).into_bytes(),
+      Format::Json => line(json_list(items.iter())),
+      Format::Env => {
+        line(items.iter().enumerate().map(|(no, item)| env(&arg_name(names, no), item)).collect::<Vec<_>>().join(" "))
+      }
+      Format::Raw => marshal(&items.iter().collect::<Vec<_>>()),
+    }
+  }
+
+  /// Render the `a{sv}` reply of `Properties.GetAll`, one property per line for text and env.
+  pub fn properties(&self, item: &MessageItem) -> Vec<u8> {
+    let entries = match *item {
+      MessageItem::Dict(ref entries) => entries,
+      _ => return Vec::new(),
+    };
+
+    match *self {
+      Format::Text | Format::Env => {
+        entries.iter()
+               .filter_map(|entry| {
+                 match *entry {
+                   (MessageItem::Str(ref name), MessageItem::Variant(ref value)) if *self == Format::Env => Some(line(env(name, value))),
+                   (MessageItem::Str(ref name), MessageItem::Variant(ref value)) => {
+                     Some(format!("{}: {}", name, value::format(value)).into_bytes())
+                   }
+                   _ => None,
+                 }
+               })
+               .collect::<Vec<_>>()
+               .concat()
+      }
+      Format::Json => line(json(item)),
+      Format::Raw => marshal(&[item]),
+    }
+  }
+}
+
+fn line(mut s: String) -> Vec<u8> {
+  s.push('\n');
+  s.into_bytes()
+}
+
+fn arg_name(names: &[&str], no: usize) -> String {
+  match names.get(no) {
+    Some(name) if !name.is_empty() => (*name).to_owned(),
+    _ => format!("arg{}", no),
+  }
+}
+
+fn env(name: &str, item: &MessageItem) -> String {
+  let text = value::format(item);
+  let text = text.trim_end_matches('\n');
+  format!("{}='{}'", name, text.replace('\'', "'\\''"))
+}
+
+fn json_list<'a, I: Iterator<Item = &'a MessageItem>>(items: I) -> String {
+  format!("[{}]", items.map(json).collect::<Vec<_>>().join(","))
+}
+
+/// Render a value as JSON: structs become arrays, dicts become objects with
+/// string keys, and variants are unwrapped. Keys are the bare path, signature or
+/// number, as string keys are.
+pub fn json(item: &MessageItem) -> String {
+  match *item {
+    MessageItem::Dict(ref entries) => {
+      let entries = entries.iter()
+                           .map(|(key, value)| {
+                             let key = match *key {
+                               MessageItem::Str(_) | MessageItem::ObjectPath(_) | MessageItem::Signature(_) => json(key),
+                               // Nothing stands for these in JSON, they keep their typed text form.
+                               MessageItem::UnixFd(_) => json_str(&value::render(key)),
+                               MessageItem::Double(d) if !d.is_finite() => json_str(&value::render(key)),
+                               ref key => json_str(&json(key)),
+                             };
+                             format!("{}:{}", key, json(value))
+                           })
+                           .collect::<Vec<_>>();
+      format!("{{{}}}", entries.join(","))
+    }
+    MessageItem::Array(ref items) => json_list(items.iter()),
+    MessageItem::Struct(ref items) => json_list(items.iter()),
+    MessageItem::Variant(ref inner) => json(inner),
+    MessageItem::ObjectPath(ref p) => json_str(p),
+    MessageItem::Signature(ref s) => json_str(s),
+    MessageItem::Str(ref s) => json_str(s),
+    MessageItem::Double(d) if !d.is_finite() => "null".to_owned(),
+    MessageItem::Double(d) => format!("{:?}", d),
+    MessageItem::Bool(b) => b.to_string(),
+    MessageItem::Byte(b) => b.to_string(),
+    MessageItem::Int16(i) => i.to_string(),
+    MessageItem::Int32(i) => i.to_string(),
+    MessageItem::Int64(i) => i.to_string(),
+    MessageItem::UInt16(i) => i.to_string(),
+    MessageItem::UInt32(i) => i.to_string(),
+    MessageItem::UInt64(i) => i.to_string(),
+    MessageItem::UnixFd(_) => "null".to_owned(),
+  }
+}
+
+fn json_str(s: &str) -> String {
+  let mut out = String::with_capacity(s.len() + 2);
+  out.push('"');
+  for c in s.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if (c as u32) < 0x20 => {
+        let _ = write!(out, "\\u{:04x}", c as u32);
+      }
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+  out
+}
+
+/// Marshal values the way they would appear in a message body.
+pub fn marshal(items: &[&MessageItem]) -> Vec<u8> {
+  let mut out = Vec::new();
+  for item in items {
+    marshal_into(&mut out, item);
+  }
+  out
+}
+
+/// Alignment of a type on the wire, by the first character of its signature.
+fn alignment(sig: &str) -> usize {
+  match sig.as_bytes().first() {
+    Some(&b'n') | Some(&b'q') => 2,
+    Some(&b'b') | Some(&b'i') | Some(&b'u') | Some(&b'h') | Some(&b's') | Some(&b'o') | Some(&b'a') => 4,
+    Some(&b'x') | Some(&b't') | Some(&b'd') | Some(&b'(') | Some(&b'{') => 8,
+    _ => 1,
+  }
+}
+
+fn pad(out: &mut Vec<u8>, align: usize) {
+  while !out.len().is_multiple_of(align) {
+    out.push(0);
+  }
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+  pad(out, 4);
+  out.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+}
+
+fn put_u64(out: &mut Vec<u8>, v: u64) {
+  pad(out, 8);
+  for shift in 0..8 {
+    out.push((v >> (shift * 8)) as u8);
+  }
+}
+
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+  pad(out, 2);
+  out.extend_from_slice(&[v as u8, (v >> 8) as u8]);
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+  put_u32(out, s.len() as u32);
+  out.extend_from_slice(s.as_bytes());
+  out.push(0);
+}
+
+fn put_sig(out: &mut Vec<u8>, s: &str) {
+  out.push(s.len() as u8);
+  out.extend_from_slice(s.as_bytes());
+  out.push(0);
+}
+
+/// Put a placeholder for the length of an array and pad to its elements, by their
+/// signature. Returns where the length goes and where the elements start, for `end_array`.
+fn begin_array(out: &mut Vec<u8>, elem_sig: &str) -> (usize, usize) {
+  put_u32(out, 0);
+  let len_at = out.len() - 4;
+  pad(out, alignment(elem_sig));
+  (len_at, out.len())
+}
+
+/// Fill in the length of an array, which leaves out the padding before its elements.
+fn end_array(out: &mut [u8], (len_at, start): (usize, usize)) {
+  let len = (out.len() - start) as u32;
+  out[len_at..len_at + 4].copy_from_slice(&[len as u8, (len >> 8) as u8, (len >> 16) as u8, (len >> 24) as u8]);
+}
+
+fn marshal_into(out: &mut Vec<u8>, item: &MessageItem) {
+  match *item {
+    MessageItem::Array(ref items) => {
+      let array = begin_array(out, &items.signature()[1..]);
+      for item in items.iter() {
+        marshal_into(out, item);
+      }
+      end_array(out, array);
+    }
+    MessageItem::Dict(ref entries) => {
+      let array = begin_array(out, "{");
+      for (key, value) in entries.iter() {
+        pad(out, 8);
+        marshal_into(out, key);
+        marshal_into(out, value);
+      }
+      end_array(out, array);
+    }
+    MessageItem::Struct(ref items) => {
+      pad(out, 8);
+      for item in items {
+        marshal_into(out, item);
+      }
+    }
+    MessageItem::Variant(ref inner) => {
+      put_sig(out, &inner.signature());
+      marshal_into(out, inner);
+    }
+    MessageItem::ObjectPath(ref p) => put_str(out, p),
+    MessageItem::Signature(ref s) => put_sig(out, s),
+    MessageItem::Str(ref s) => put_str(out, s),
+    MessageItem::Bool(b) => put_u32(out, b as u32),
+    MessageItem::Byte(b) => out.push(b),
+    MessageItem::Int16(i) => put_u16(out, i as u16),
+    MessageItem::UInt16(i) => put_u16(out, i),
+    MessageItem::Int32(i) => put_u32(out, i as u32),
+    MessageItem::UInt32(i) => put_u32(out, i),
+    MessageItem::Int64(i) => put_u64(out, i as u64),
+    MessageItem::UInt64(i) => put_u64(out, i),
+    MessageItem::Double(d) => put_u64(out, d.to_bits()),
+    // Unix fds are sent out of band, the body only holds an index into them.
+    MessageItem::UnixFd(_) => put_u32(out, 0),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use node::types::Full;
+  use value;
+
+  fn parse(sig: &str, text: &str) -> MessageItem {
+    value::parse(text, &sig.parse::<Full>().unwrap()).unwrap()
+  }
+
+  #[test]
+  fn json_dicts() {
+    assert_eq!(json(&parse("a{sv}", r#"{"a": <@i 1>, "b": <@as ["x"]>}"#)), r#"{"a":1,"b":["x"]}"#);
+    assert_eq!(json(&parse("a{ib}", "{1: true}")), r#"{"1":true}"#);
+    assert_eq!(json(&parse("a{oa{sv}}", r#"{objectpath "/org/x": {"a": <@u 1>}, objectpath "/": {}}"#)),
+               r#"{"/org/x":{"a":1},"/":{}}"#);
+    assert_eq!(json(&parse("a{gb}", r#"{signature "a{sv}": false}"#)), r#"{"a{sv}":false}"#);
+    assert_eq!(json(&parse("a{tb}", "{18446744073709551615: true}")), r#"{"18446744073709551615":true}"#);
+    assert_eq!(json(&parse("a{bi}", "{true: 1}")), r#"{"true":1}"#);
+    assert_eq!(json(&parse("a(is)", r#"[(1, "x")]"#)), r#"[[1,"x"]]"#);
+  }
+
+  #[test]
+  fn marshal_dicts() {
+    // Entries are 8-aligned even in an empty dict, and the length leaves the padding out.
+    assert_eq!(marshal(&[&parse("a{sv}", r#"{"a": <@i 1>}"#)]),
+               vec![16, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, b'a', 0, 1, b'i', 0, 0, 0, 0, 1, 0, 0, 0]);
+    assert_eq!(marshal(&[&parse("a{sv}", "{}")]), vec![0, 0, 0, 0, 0, 0, 0, 0]);
+  }
+
+  #[test]
+  fn marshal_arrays() {
+    assert_eq!(marshal(&[&parse("ay", r#"b"ab""#)]), vec![2, 0, 0, 0, b'a', b'b']);
+    assert_eq!(marshal(&[&parse("y", "1"), &parse("ax", "[2]")]),
+               vec![1, 0, 0, 0, 8, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(marshal(&[&parse("g", "ai")]), vec![2, b'a', b'i', 0]);
+  }
+}
@@ -0,0 +1,148 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read};
+
+use filter::Pattern;
+
+/// Methods guarded unless told otherwise, as `interface.Member` globs.
+static DEFAULT_GUARDS: &[&str] = &["org.freedesktop.login1.Manager.PowerOff",
+                                   "org.freedesktop.login1.Manager.Reboot",
+                                   "org.freedesktop.login1.Manager.Halt",
+                                   "org.freedesktop.login1.Manager.KExec",
+                                   "org.freedesktop.login1.Manager.Suspend",
+                                   "org.freedesktop.login1.Manager.Hibernate",
+                                   "org.freedesktop.login1.Manager.HybridSleep",
+                                   "org.freedesktop.login1.Manager.TerminateSession",
+                                   "org.freedesktop.login1.Manager.TerminateUser",
+                                   "org.freedesktop.login1.Manager.TerminateSeat",
+                                   "org.freedesktop.systemd1.Manager.StopUnit",
+                                   "org.freedesktop.systemd1.Manager.KillUnit",
+                                   "org.freedesktop.systemd1.Manager.PowerOff",
+                                   "org.freedesktop.systemd1.Manager.Reboot",
+                                   "org.freedesktop.systemd1.Manager.Halt",
+                                   "org.freedesktop.systemd1.Manager.KExec",
+                                   "org.freedesktop.systemd1.Manager.Exit",
+                                   "org.freedesktop.systemd1.Unit.Stop",
+                                   "org.freedesktop.systemd1.Unit.Kill"];
+
+/// Methods that must not be called by accident.
+///
+/// A guarded method is refused with EPERM, unless guards are off for the whole
+/// mount or the call has been confirmed: reading `Member.confirm` hands out a
+/// token, and writing it back allows the next call of that method only.
+pub struct Guard {
+  patterns: Vec<Pattern>,
+  allow: bool,
+  tokens: HashMap<String, String>,
+  confirmed: HashSet<String>,
+}
+
+impl Default for Guard {
+  fn default() -> Guard {
+    Guard {
+      patterns: DEFAULT_GUARDS.iter().map(|glob| Pattern::Glob((*glob).to_owned())).collect(),
+      allow: false,
+      tokens: HashMap::new(),
+      confirmed: HashSet::new(),
+    }
+  }
+}
+
+impl Guard {
+  pub fn add(&mut self, pattern: &str) -> Result<(), String> {
+    self.patterns.push(Pattern::new(pattern)?);
+    Ok(())
+  }
+
+  pub fn clear(&mut self) {
+    self.patterns.clear();
+  }
+
+  /// Let every guarded method be called, as with `--allow-guarded`.
+  pub fn allow_all(&mut self) {
+    self.allow = true;
+  }
+
+  /// Whether calls of `iface.member` need a confirmation.
+  pub fn guards(&self, iface: &str, member: &str) -> bool {
+    if self.allow {
+      return false;
+    }
+    let name = format!("{}.{}", iface, member);
+    self.patterns.iter().any(|p| p.matches(&name))
+  }
+
+  /// Hand out a new token for a method, `key` tells methods on different objects apart.
+  /// Without random bytes there is no token, a guessable one would be no guard.
+  pub fn token(&mut self, key: &str) -> io::Result<String> {
+    let mut bytes = [0; 8];
+    File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes))?;
+    let token = bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    self.tokens.insert(key.to_owned(), token.clone());
+    Ok(token)
+  }
+
+  /// Confirm the next call with a token handed out before, which can't be used again.
+  pub fn confirm(&mut self, key: &str, token: &str) -> bool {
+    if self.tokens.get(key).is_some_and(|t| t == token) {
+      self.tokens.remove(key);
+      self.confirmed.insert(key.to_owned());
+      true
+    } else {
+      false
+    }
+  }
+
+  /// Check a call, using up its confirmation.
+  pub fn permit(&mut self, key: &str, iface: &str, member: &str) -> bool {
+    !self.guards(iface, member) || self.confirmed.remove(key)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Guard;
+
+  static KEY: &str = "0 org.freedesktop.login1 /org/freedesktop/login1 org.freedesktop.login1.Manager.PowerOff";
+  static IFACE: &str = "org.freedesktop.login1.Manager";
+
+  #[test]
+  fn guarded_calls_need_a_token() {
+    let mut guard = Guard::default();
+    assert!(!guard.permit(KEY, IFACE, "PowerOff"));
+    assert!(guard.permit(KEY, IFACE, "ListSessions"));
+
+    guard.allow_all();
+    assert!(guard.permit(KEY, IFACE, "PowerOff"));
+  }
+
+  #[test]
+  fn wrong_tokens_are_refused() {
+    let mut guard = Guard::default();
+    let token = guard.token(KEY).unwrap();
+    assert_eq!(token.len(), 16);
+    assert!(!guard.confirm(KEY, "0000000000000000"));
+    assert!(!guard.confirm("other key", &token));
+    assert!(!guard.permit(KEY, IFACE, "PowerOff"));
+  }
+
+  #[test]
+  fn tokens_allow_one_call() {
+    let mut guard = Guard::default();
+    let token = guard.token(KEY).unwrap();
+    assert!(guard.confirm(KEY, &token));
+    assert!(!guard.confirm(KEY, &token));
+    assert!(guard.permit(KEY, IFACE, "PowerOff"));
+    assert!(!guard.permit(KEY, IFACE, "PowerOff"));
+  }
+
+  #[test]
+  fn new_tokens_replace_old_ones() {
+    let mut guard = Guard::default();
+    let old = guard.token(KEY).unwrap();
+    let new = guard.token(KEY).unwrap();
+    assert_ne!(old, new);
+    assert!(!guard.confirm(KEY, &old));
+    assert!(guard.confirm(KEY, &new));
+  }
+}
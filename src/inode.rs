@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use dbus::Path;
+use dbus::strings::{BusName, Interface};
+use fuser::FileAttr;
+
+use format::Format;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NodeKind {
+  Bus,
+  Destination,
+  ObjectPath,
+  Interface,
+  Method,
+  Signal,
+  Property,
+  Properties,
+  Annotation,
+  Confirm,
+}
+
+/// What an inode stands for on the bus.
+///
+/// `bus` is an index into the mounted buses, a `Bus` node stands for the bus daemon.
+/// `member` is a plain string, as annotation names are not valid member names.
+/// `format` is set when the file name carries a format suffix.
+#[derive(Debug, Clone)]
+pub struct Node {
+  pub kind: NodeKind,
+  pub bus: usize,
+  pub dest: BusName<'static>,
+  pub path: Path<'static>,
+  pub iface: Option<Interface<'static>>,
+  pub member: Option<String>,
+  pub format: Option<Format>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+  pub parent: u64,
+  pub name: String,
+  pub node: Node,
+  pub attr: FileAttr,
+  pub generation: u64,
+  lookups: u64,
+}
+
+/// All inodes known to the kernel, indexed both by inode number and by
+/// parent inode and name.
+pub struct NodeTable {
+  entries: HashMap<u64, Entry>,
+  names: HashMap<(u64, String), u64>,
+  last_inode: u64,
+  generation: u64,
+}
+
+impl Node {
+  pub fn new(kind: NodeKind, bus: usize, dest: BusName<'static>, path: Path<'static>) -> Node {
+    Node {
+      kind,
+      bus,
+      dest,
+      path,
+      iface: None,
+      member: None,
+      format: None,
+    }
+  }
+
+  pub fn child(&self, kind: NodeKind, member: &str) -> Node {
+    Node {
+      kind,
+      bus: self.bus,
+      dest: self.dest.clone(),
+      path: self.path.clone(),
+      iface: self.iface.clone(),
+      member: Some(member.to_owned()),
+      format: None,
+    }
+  }
+}
+
+impl NodeTable {
+  pub fn new() -> NodeTable {
+    NodeTable {
+      entries: HashMap::new(),
+      names: HashMap::new(),
+      last_inode: 1,
+      generation: 0,
+    }
+  }
+
+  pub fn next_inode(&mut self) -> u64 {
+    self.last_inode += 1;
+    self.last_inode
+  }
+
+  pub fn get(&self, ino: u64) -> Option<&Entry> {
+    self.entries.get(&ino)
+  }
+
+  pub fn get_mut(&mut self, ino: u64) -> Option<&mut Entry> {
+    self.entries.get_mut(&ino)
+  }
+
+  pub fn find(&self, parent: u64, name: &str) -> Option<&Entry> {
+    self.names.get(&(parent, name.to_owned())).and_then(|ino| self.entries.get(ino))
+  }
+
+  /// Add an entry for `attr.ino` under the given parent and name.
+  ///
+  /// A previous entry with the same name stays reachable by its inode number
+  /// until the kernel forgets it.
+  pub fn insert(&mut self, parent: u64, name: &str, node: Node, attr: FileAttr) -> &Entry {
+    let ino = attr.ino;
+    if let Some(old) = self.names.insert((parent, name.to_owned()), ino) {
+      if self.entries.get(&old).is_some_and(|e| e.lookups == 0) {
+        self.entries.remove(&old);
+      }
+    }
+
+    self.entries.insert(ino,
+                        Entry {
+                          parent,
+                          name: name.to_owned(),
+                          node,
+                          attr,
+                          generation: self.generation,
+                          lookups: 0,
+                        });
+    &self.entries[&ino]
+  }
+
+  /// Take stale entries off their names, so that they are looked up again. Like a
+  /// replaced entry, each stays reachable by its inode number until forgotten.
+  ///
+  /// Returns the parent and name of those the kernel holds, for it to drop them too.
+  pub fn unlink<F: Fn(&Node) -> bool>(&mut self, stale: F) -> Vec<(u64, String)> {
+    let inos = self.entries.iter().filter(|&(_, e)| stale(&e.node)).map(|(&ino, _)| ino).collect::<Vec<_>>();
+    let mut held = Vec::new();
+    for ino in inos {
+      let (key, unused) = {
+        let entry = &self.entries[&ino];
+        ((entry.parent, entry.name.clone()), entry.lookups == 0)
+      };
+      if self.names.get(&key) == Some(&ino) {
+        self.names.remove(&key);
+      }
+      if unused {
+        self.entries.remove(&ino);
+      } else {
+        held.push(key);
+      }
+    }
+    held
+  }
+
+  /// Path of an inode the kernel holds, relative to the mountpoint.
+  pub fn path(&self, ino: u64) -> Option<PathBuf> {
+    let mut names = Vec::new();
+    let mut ino = ino;
+    while ino != 1 {
+      let entry = match self.entries.get(&ino) {
+        Some(entry) if entry.lookups > 0 => entry,
+        _ => return None,
+      };
+      names.push(&*entry.name);
+      ino = entry.parent;
+    }
+    Some(names.into_iter().rev().collect())
+  }
+
+  /// Count a reference handed out to the kernel with a lookup reply.
+  pub fn lookup(&mut self, ino: u64) {
+    if let Some(entry) = self.entries.get_mut(&ino) {
+      entry.lookups += 1;
+    }
+  }
+
+  /// Drop `nlookup` kernel references, freeing the entry when none are left.
+  pub fn forget(&mut self, ino: u64, nlookup: u64) {
+    let gone = match self.entries.get_mut(&ino) {
+      Some(entry) => {
+        entry.lookups = entry.lookups.saturating_sub(nlookup);
+        entry.lookups == 0
+      }
+      None => false,
+    };
+
+    if gone {
+      if let Some(entry) = self.entries.remove(&ino) {
+        let key = (entry.parent, entry.name);
+        if self.names.get(&key) == Some(&ino) {
+          self.names.remove(&key);
+        }
+      }
+      self.generation += 1;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::UNIX_EPOCH;
+
+  use dbus::Path;
+  use dbus::strings::BusName;
+  use fuser::{FileAttr, FileType};
+
+  use super::{Node, NodeKind, NodeTable};
+
+  fn node(path: &str) -> Node {
+    Node::new(NodeKind::ObjectPath, 0, BusName::new("org.x").unwrap(), Path::new(path.to_owned()).unwrap())
+  }
+
+  fn attr(ino: u64) -> FileAttr {
+    FileAttr {
+      ino,
+      size: 0,
+      blocks: 0,
+      atime: UNIX_EPOCH,
+      mtime: UNIX_EPOCH,
+      ctime: UNIX_EPOCH,
+      crtime: UNIX_EPOCH,
+      kind: FileType::Directory,
+      perm: 0o755,
+      nlink: 2,
+      uid: 0,
+      gid: 0,
+      rdev: 0,
+      blksize: 512,
+      flags: 0,
+    }
+  }
+
+  fn insert(nodes: &mut NodeTable, parent: u64, name: &str, path: &str) -> u64 {
+    let ino = nodes.next_inode();
+    nodes.insert(parent, name, node(path), attr(ino)).attr.ino
+  }
+
+  #[test]
+  fn replaced_entries_stay_reachable_while_held() {
+    let mut nodes = NodeTable::new();
+    let old = insert(&mut nodes, 1, "x", "/x");
+    nodes.lookup(old);
+    let new = insert(&mut nodes, 1, "x", "/x");
+
+    assert_eq!(nodes.find(1, "x").map(|e| e.attr.ino), Some(new));
+    assert_eq!(nodes.get(old).map(|e| &*e.name), Some("x"));
+
+    // Nobody holds the one replaced now, it's gone at once.
+    insert(&mut nodes, 1, "x", "/x");
+    assert!(nodes.get(new).is_none());
+  }
+
+  #[test]
+  fn unlink_returns_the_held_entries() {
+    let mut nodes = NodeTable::new();
+    let held = insert(&mut nodes, 1, "held", "/x");
+    let unused = insert(&mut nodes, 1, "unused", "/x");
+    let other = insert(&mut nodes, 1, "other", "/y");
+    nodes.lookup(held);
+    nodes.lookup(other);
+
+    assert_eq!(nodes.unlink(|node| &*node.path == "/x"), vec![(1, "held".to_owned())]);
+    assert!(nodes.find(1, "held").is_none());
+    assert!(nodes.get(held).is_some());
+    assert!(nodes.find(1, "unused").is_none());
+    assert!(nodes.get(unused).is_none());
+    assert_eq!(nodes.find(1, "other").map(|e| e.attr.ino), Some(other));
+  }
+
+  #[test]
+  fn forget_keeps_a_name_taken_over() {
+    let mut nodes = NodeTable::new();
+    let old = insert(&mut nodes, 1, "x", "/x");
+    nodes.lookup(old);
+    nodes.lookup(old);
+    let new = insert(&mut nodes, 1, "x", "/x");
+
+    nodes.forget(old, 1);
+    assert!(nodes.get(old).is_some());
+    nodes.forget(old, 1);
+    assert!(nodes.get(old).is_none());
+    assert_eq!(nodes.find(1, "x").map(|e| e.attr.ino), Some(new));
+
+    nodes.lookup(new);
+    nodes.forget(new, 1);
+    assert!(nodes.get(new).is_none());
+    assert!(nodes.find(1, "x").is_none());
+  }
+}
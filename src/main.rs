@@ -4,181 +4,745 @@
 extern crate users;
 extern crate time;
 extern crate dbus;
-extern crate fuse;
+extern crate fuser;
+extern crate getopts;
+extern crate regex;
 extern crate libc;
 extern crate xml;
 
+use std::cell::RefCell;
+use std::cmp;
 use std::env;
+use std::ffi::{CString, OsStr};
+use std::io::{self, Write};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::process;
+use std::str;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use time::Timespec;
-use dbus::{BusType, Connection, Message, MessageItem};
-use fuse::{FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request};
-use libc::{EACCES, ENOENT};
+use dbus::{Message, MessageType};
+use dbus::arg::messageitem::{MessageItem, MessageItemDict};
+use dbus::channel::{self, Channel};
+use dbus::strings::{BusName, Member};
+use fuser::{FileAttr, FileType, Filesystem, KernelConfig, Notifier, PollHandle, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry,
+            ReplyOpen, ReplyPoll, ReplyWrite, ReplyXattr, Request, Session, TimeOrNow};
+use fuser::consts::FUSE_POLL_SCHEDULE_NOTIFY;
+use libc::{c_int, EACCES, EAGAIN, EBADF, EFBIG, EINVAL, EIO, ENODATA, ENOENT, EINTR, ENOTDIR, EOPNOTSUPP, EPERM, ERANGE, EROFS, O_ACCMODE, O_NONBLOCK, O_RDONLY, O_WRONLY,
+           POLLIN, POLLOUT, POLLRDNORM, POLLWRNORM};
 use users::get_user_by_uid;
-use node::NodeInfo;
+use node::{Access, EmitsChanged, Interface, Method, NodeInfo, Property, Signal};
+#[cfg(not(feature = "readonly"))]
+use node::Direction;
+use inode::{Node, NodeKind, NodeTable};
+use signal::{PropertyWatch, SignalWatch};
+use format::Format;
+use bus::Bus;
+use cache::{Cache, Change};
+use daemon::Daemon;
+use errno::Errnos;
+use filter::{Filter, Scope};
+use guard::Guard;
+use timeout::Timeouts;
 
+mod bus;
+mod cache;
+mod cli;
+mod daemon;
+mod errno;
+mod filter;
+mod format;
+mod guard;
+mod inode;
 mod node;
+mod signal;
+mod timeout;
+// Only formatting is left with the `readonly` feature, nothing is ever parsed.
+#[cfg_attr(feature = "readonly", allow(dead_code))]
+mod value;
+mod xattr;
 
-enum NodeKind {
-  Destination,
-  ObjectPath,
-  Interface,
-  Method,
-  Signal,
-  Property,
-  Annotation,
+/// Per-open state of a file.
+enum Handle {
+  /// Text written to a property file, sent with `Properties.Set` on flush.
+  ///
+  /// Opened read-only with `O_NONBLOCK` the file is watched: `poll` reports it
+  /// readable until it's read, and again whenever `PropertiesChanged` changes the value.
+  Property {
+    ino: u64,
+    buffer: Vec<u8>,
+    dirty: bool,
+    watching: bool,
+    changed: bool,
+    poll: Option<PollHandle>,
+  },
+  /// Arguments written to a method file and the reply to the call made with them.
+  ///
+  /// Call files behave like a pipe rather than a regular file: writes append to
+  /// the input and reads consume the reply from `position`, ignoring offsets.
+  ///
+  /// Nothing is called before something has been written, even just a newline,
+  /// so that `grep -r` and the like reading every file call nothing. The call is
+  /// made by the first read after a write, or on close when no read came.
+  Method {
+    input: Vec<u8>,
+    written: bool,
+    output: Option<Result<Vec<u8>, c_int>>,
+    position: usize,
+  },
+  /// Signals received for a signal file and not read yet, one line each, and the
+  /// reads waiting for one, with the thread reading and the size it asked for.
+  ///
+  /// `poll` reports the file readable while lines are queued. Read with `O_NONBLOCK`,
+  /// an empty queue is `EAGAIN`.
+  Signal {
+    bus: usize,
+    watch: SignalWatch,
+    queue: VecDeque<Vec<u8>>,
+    waiting: VecDeque<(u32, u32, ReplyData)>,
+    poll: Option<PollHandle>,
+  },
+  /// Token handed out for confirming a guarded call, and what has been written back.
+  Confirm { key: String, token: Vec<u8>, input: Vec<u8> },
 }
 
 struct DbusFs {
-  dbus: Connection,
-  inodes: HashMap<(u64, PathBuf), u64>,
-  inode_attr: HashMap<u64, FileAttr>,
-  inode_name: HashMap<u64, (NodeKind, dbus::BusName, dbus::Path, Option<dbus::Interface>, Option<dbus::Member>)>,
-  last_inode: AtomicUsize,
+  /// Mounted buses and the names of their directories. With a single bus its
+  /// names are listed right in the root directory.
+  buses: Vec<(String, Channel)>,
+  nodes: NodeTable,
+  handles: HashMap<u64, Handle>,
+  last_handle: AtomicUsize,
+  matches: HashMap<(usize, String), usize>,
+  /// Format of files without a format suffix.
+  format: Format,
+  /// Refuse everything that could change something on the bus with EROFS.
+  read_only: bool,
+  /// What is hidden from listings and lookups.
+  filter: Filter,
+  /// Methods refused unless confirmed.
+  guard: Guard,
+  /// How long calls wait for a reply.
+  timeouts: Timeouts,
+  /// Errnos D-Bus errors are reported as.
+  errnos: Errnos,
+  /// The last D-Bus error, until it's kept for the file it happened on.
+  last_error: RefCell<Option<String>>,
+  /// Last D-Bus error of each file, in `user.dbus.last_error`.
+  errors: HashMap<u64, String>,
+  /// Introspection data of recently seen objects.
+  cache: Cache,
+  /// How long the kernel may keep entries and attributes, the bus thread tells it
+  /// when they change before that.
+  ttl: Duration,
+  /// Looked up property files following `PropertiesChanged`, by inode.
+  properties: HashMap<u64, PropertyWatch>,
+  /// Where the file system is mounted, changed files are touched through it.
+  mountpoint: PathBuf,
+  /// What the bus thread is to tell the kernel once it has let go of the file system.
+  notices: Vec<Notice>,
+  daemon: Daemon,
 }
 
-static DBUS_INSPECT_DEST: &'static str = "org.freedesktop.DBus";
-static DBUS_INSPECT_IFACE: &'static str = "org.freedesktop.DBus";
-static DBUS_INSPECT_PATH: &'static str = "/org/freedesktop/DBus";
-static DBUS_INTROSPECT_IFACE: &'static str = "org.freedesktop.DBus.Introspectable";
-static DBUS_ACCESS_ERROR: &'static str = "org.freedesktop.DBus.Error.AccessDenied";
-
-static ROOT_DIR: FileAttr = FileAttr {
-  ino: 1,
-  size: 0,
-  blocks: 0,
-  atime: CREATE_TIME,
-  mtime: CREATE_TIME,
-  ctime: CREATE_TIME,
-  crtime: CREATE_TIME,
-  kind: FileType::Directory,
-  perm: 0o755,
-  nlink: 2,
-  uid: 0,
-  gid: 0,
-  rdev: 0,
-  flags: 0,
-};
+/// A change the kernel has to hear about. Telling it while a request waits for
+/// the file system would deadlock, as the kernel may wait for that request first.
+enum Notice {
+  /// An entry gone stale, by parent inode and name, to be looked up again.
+  Entry(u64, String),
+  /// A file or directory that changed, with its path in the mount.
+  Inode(u64, PathBuf),
+}
+
+static DBUS_INSPECT_DEST: &str = "org.freedesktop.DBus";
+static DBUS_INSPECT_IFACE: &str = "org.freedesktop.DBus";
+static DBUS_INSPECT_PATH: &str = "/org/freedesktop/DBus";
+static DBUS_INTROSPECT_IFACE: &str = "org.freedesktop.DBus.Introspectable";
+static DBUS_PROPERTIES_IFACE: &str = "org.freedesktop.DBus.Properties";
+static DBUS_ACCESS_ERROR: &str = "org.freedesktop.DBus.Error.AccessDenied";
+
+/// Name of the file with all properties of an interface, `@` never occurs in member names.
+static PROPERTIES_FILE: &str = "@properties";
+
+/// Suffix of the file confirming a call of a guarded method, `PowerOff.confirm`.
+static CONFIRM_SUFFIX: &str = ".confirm";
 
 impl Default for DbusFs {
   fn default() -> DbusFs {
-    DbusFs::new(BusType::System).unwrap()
+    DbusFs::new(&[Bus::default()]).unwrap()
   }
 }
 
 impl DbusFs {
-  fn new(bus: BusType) -> Result<DbusFs, dbus::Error> {
-    Connection::get_private(bus).map(DbusFs::from_connection)
+  fn new(buses: &[Bus]) -> Result<DbusFs, dbus::Error> {
+    let conns = bus::connect_all(buses)?;
+    Ok(DbusFs::from_connections(bus::dir_names(buses).into_iter().zip(conns).collect()))
   }
 
-  fn from_connection(conn: Connection) -> DbusFs {
+  fn from_connections(conns: Vec<(String, Channel)>) -> DbusFs {
     DbusFs {
-      dbus: conn,
-      inodes: HashMap::new(),
-      inode_attr: HashMap::new(),
-      inode_name: HashMap::new(),
-      last_inode: AtomicUsize::new(2),
+      buses: conns,
+      nodes: NodeTable::new(),
+      handles: HashMap::new(),
+      last_handle: AtomicUsize::new(1),
+      matches: HashMap::new(),
+      format: Format::default(),
+      read_only: false,
+      filter: Filter::default(),
+      guard: Guard::default(),
+      timeouts: Timeouts::default(),
+      errnos: Errnos::default(),
+      last_error: RefCell::new(None),
+      errors: HashMap::new(),
+      cache: Cache::default(),
+      ttl: Duration::from_secs(cache::DEFAULT_KERNEL_TTL as u64),
+      properties: HashMap::new(),
+      mountpoint: PathBuf::new(),
+      notices: Vec::new(),
+      daemon: Daemon::default(),
     }
   }
 
-  fn make_inode<P: AsRef<Path>>(&mut self, path: P) -> Option<&FileAttr> {
-    let path = path.as_ref();
-    if let Some((ino, _)) = self.inodes.iter().find(|&(_, p)| p == path) {
-      return Some(&self.inode_attr[ino]);
+  fn next_handle(&self) -> u64 {
+    self.last_handle.fetch_add(1, Ordering::SeqCst) as u64
+  }
+
+  /// Read-only either at runtime or for good, with the `readonly` feature.
+  fn read_only(&self) -> bool {
+    cfg!(feature = "readonly") || self.read_only
+  }
+
+  /// The errno to report a D-Bus error as. The error itself is remembered until
+  /// `keep_error` files it under the inode it happened on.
+  fn errno(&self, err: &dbus::Error) -> c_int {
+    *self.last_error.borrow_mut() = Some(format!("{}: {}",
+                                                 err.name().unwrap_or("unknown error"),
+                                                 err.message().unwrap_or("")));
+    self.errnos.errno(err.name())
+  }
+
+  /// Keep the D-Bus error behind a failure on a file, if there is one, and pass its errno on.
+  fn keep_error(&mut self, ino: u64, errno: c_int) -> c_int {
+    if let Some(err) = self.last_error.borrow_mut().take() {
+      self.errors.insert(ino, err);
     }
+    errno
+  }
+
+  fn conn(&self, bus: usize) -> &Channel {
+    &self.buses[bus].1
+  }
 
-    let ino = self.last_inode.fetch_add(1, Ordering::SeqCst) as u64;
+  /// Send a method call and wait up to `timeout_ms` for its reply.
+  fn call(&self, bus: usize, msg: Message, timeout_ms: i32) -> Result<Message, dbus::Error> {
+    self.conn(bus).send_with_reply_and_block(msg, Duration::from_millis(timeout_ms as u64))
+  }
 
-    let (dest, object) = match split_path(path) {
-      Some((d, o)) => (d, o),
-      None => return None,
-    };
+  /// The bus whose names a directory lists: the root with a single bus, or a bus directory.
+  fn names_dir(&self, ino: u64) -> Option<usize> {
+    if ino == 1 {
+      return if self.buses.len() == 1 {
+        Some(0)
+      } else {
+        None
+      };
+    }
+
+    match self.node_by_inode(ino) {
+      Some(&Node { kind: NodeKind::Bus, bus, .. }) => Some(bus),
+      _ => None,
+    }
+  }
 
-    let uid = self.get_connection_unix_user(&dest).unwrap_or(0);
+  fn object_attr(&self, ino: u64, bus: usize, dest: &BusName, object: &dbus::Path) -> Result<FileAttr, c_int> {
+    let uid = self.get_connection_unix_user(bus, dest).unwrap_or(0);
     let gid = get_user_by_uid(uid).map_or(0, |u| u.primary_group);
 
-    let (nlink, perm) = match self.introspect(dest, object) {
+    let (nlink, perm) = match self.introspect(bus, dest.clone(), object.clone()) {
       Ok(Some(node_info)) => (node_info.nodes.len() as u32, 0o755),
       Err(ref err) if err.name() == Some(DBUS_ACCESS_ERROR) => (0, 0o750),
-      _ => return None,
+      Err(ref err) => return Err(self.errno(err)),
+      Ok(None) => return Err(ENOENT),
     };
 
-    let attr = FileAttr {
-      ino: ino,
+    let created = create_time();
+    Ok(FileAttr {
+      ino,
       size: 0,
       blocks: 1,
-      atime: CREATE_TIME,
-      mtime: CREATE_TIME,
-      ctime: CREATE_TIME,
-      crtime: CREATE_TIME,
+      atime: created,
+      mtime: created,
+      ctime: created,
+      crtime: created,
       kind: FileType::Directory,
-      perm: perm,
-      nlink: nlink,
-      uid: uid,
-      gid: gid,
+      perm,
+      nlink,
+      uid,
+      gid,
       rdev: 0,
+      blksize: BLOCK_SIZE,
       flags: 0,
+    })
+  }
+
+  fn property_attr(&self, ino: u64, node: &Node, prop: &Property) -> FileAttr {
+    let mut attr = self.file_attr_file(ino);
+    attr.perm = match prop.access {
+      Access::Read => 0o444,
+      Access::Write => 0o222,
+      Access::ReadWrite => 0o644,
     };
+    // Only the text format can be parsed back.
+    if self.format_of(node) != Format::Text || self.read_only() {
+      attr.perm &= 0o444;
+    }
+    if let Some(watch) = self.properties.get(&ino) {
+      attr.mtime = watch.changed;
+      attr.ctime = watch.changed;
+    }
+    if prop.access != Access::Write {
+      attr.size = self.read_property(ino, node, prop).ok().and_then(|v| v).map_or(0, |v| v.len() as u64);
+    }
+    attr
+  }
 
-    self.inodes.insert(ino, path.to_owned());
-    self.inode_attr.insert(ino, attr);
-    self.inode_attr.get(&ino)
+  fn properties_attr(&self, ino: u64, node: &Node) -> FileAttr {
+    let mut attr = self.file_attr_file(ino);
+    attr.perm = 0o444;
+    attr.size = self.read_properties(node).ok().and_then(|v| v).map_or(0, |v| v.len() as u64);
+    attr
   }
 
-  fn file_attr_dir(&mut self, ino: u64) -> FileAttr {
+  fn annotation_attr(&self, ino: u64, node: &Node) -> FileAttr {
+    let mut attr = self.file_attr_file(ino);
+    attr.perm = 0o444;
+    attr.size = self.read_annotation(node).map_or(0, |v| v.len() as u64);
+    attr
+  }
+
+  fn file_attr_dir(&self, ino: u64) -> FileAttr {
+    let created = create_time();
     FileAttr {
-      ino: ino,
+      ino,
       size: 0,
       blocks: 1,
-      atime: CREATE_TIME,
-      mtime: CREATE_TIME,
-      ctime: CREATE_TIME,
-      crtime: CREATE_TIME,
+      atime: created,
+      mtime: created,
+      ctime: created,
+      crtime: created,
       kind: FileType::Directory,
       perm: 0o755,
       nlink: 2,
       uid: 0,
       gid: 0,
       rdev: 0,
+      blksize: BLOCK_SIZE,
       flags: 0,
     }
   }
-  fn file_attr_file(&mut self, ino: u64) -> FileAttr {
+  fn file_attr_file(&self, ino: u64) -> FileAttr {
+    let created = create_time();
     FileAttr {
-      ino: ino,
+      ino,
       size: 0,
       blocks: 1,
-      atime: CREATE_TIME,
-      mtime: CREATE_TIME,
-      ctime: CREATE_TIME,
-      crtime: CREATE_TIME,
-      kind: FileType::Directory,
+      atime: created,
+      mtime: created,
+      ctime: created,
+      crtime: created,
+      kind: FileType::RegularFile,
       perm: 0o644,
       nlink: 1,
       uid: 0,
       gid: 0,
       rdev: 0,
+      blksize: BLOCK_SIZE,
       flags: 0,
     }
   }
 
-  fn path_by_inode(&self, ino: u64) -> Option<&Path> {
-    self.inodes.get(&ino).map(PathBuf::as_path)
+  fn format_of(&self, node: &Node) -> Format {
+    node.format.unwrap_or(self.format)
+  }
+
+  fn node_by_inode(&self, ino: u64) -> Option<&Node> {
+    self.nodes.get(ino).map(|e| &e.node)
   }
 
   fn attr_by_inode(&self, ino: u64) -> Option<&FileAttr> {
-    self.inode_attr.get(&ino)
+    self.nodes.get(ino).map(|e| &e.attr)
+  }
+
+  /// Attributes of a file as they are now, property files get their size and times again.
+  fn current_attr(&mut self, ino: u64) -> Option<FileAttr> {
+    if ino == 1 {
+      return Some(self.file_attr_dir(1));
+    }
+
+    let node = match self.node_by_inode(ino) {
+      Some(node) => node.clone(),
+      None => return None,
+    };
+
+    let attr = match node.kind {
+      NodeKind::Property => self.find_property(&node).ok().map(|prop| self.property_attr(ino, &node, &prop)),
+      NodeKind::Properties => Some(self.properties_attr(ino, &node)),
+      _ => None,
+    };
+    if let (Some(attr), Some(entry)) = (attr, self.nodes.get_mut(ino)) {
+      entry.attr = attr;
+    }
+    // The attributes cached before are as good as an error here.
+    self.last_error.borrow_mut().take();
+
+    self.attr_by_inode(ino).cloned()
+  }
+
+  /// Names and types of all entries of a directory: buses in the root when there
+  /// are several, bus names in a bus, child nodes and interfaces in an object path,
+  /// and members in an interface.
+  fn list_dir(&self, ino: u64) -> Result<Vec<(String, FileType)>, c_int> {
+    if let Some(bus) = self.names_dir(ino) {
+      let names = self.list_names(bus).map_err(|e| self.errno(&e))?;
+      let mut shown = names.iter().filter(|name| self.filter.allows(Scope::Dest, name)).cloned().collect::<Vec<_>>();
+      if shown.iter().any(|name| name.starts_with(':')) {
+        let hidden = self.hidden_owners(bus, &names);
+        shown.retain(|name| !hidden.contains(name));
+      }
+      return Ok(shown.into_iter().map(|name| (name, FileType::Directory)).collect());
+    }
+
+    if ino == 1 {
+      return Ok(self.buses.iter().map(|(name, _)| (name.clone(), FileType::Directory)).collect());
+    }
+
+    let node = match self.node_by_inode(ino) {
+      Some(node) => node,
+      None => return Err(ENOENT),
+    };
+
+    let node_info = match self.introspect(node.bus, node.dest.clone(), node.path.clone()) {
+      Ok(Some(info)) => info,
+      Ok(None) => return Err(ENOENT),
+      Err(ref e) => return Err(self.errno(e)),
+    };
+
+    match node.kind {
+      NodeKind::Destination | NodeKind::ObjectPath => {
+        let path = &node.path;
+        Ok(node_info.nodes
+                    .into_iter()
+                    .map(|n| n.name)
+                    .filter(|name| child_path(path, name).is_some_and(|p| self.filter.allows(Scope::Path, &p)))
+                    .chain(node_info.interfaces.into_iter().map(|i| i.name).filter(|name| self.filter.allows(Scope::Iface, name)))
+                    .map(|name| (name, FileType::Directory))
+                    .collect())
+      }
+
+      NodeKind::Interface => {
+        match node_info.interfaces.into_iter().find(|i| Some(&*i.name) == node.iface.as_deref()) {
+          Some(iface) => {
+            let all = if !iface.properties.iter().any(|p| self.filter.allows(Scope::Member, &p.name)) {
+              None
+            } else {
+              Some(PROPERTIES_FILE.to_owned())
+            };
+            let confirms = iface.methods
+                                .iter()
+                                .filter(|m| valid_member(&m.name))
+                                .filter(|m| self.filter.allows(Scope::Member, &m.name) && self.guard.guards(&iface.name, &m.name))
+                                .map(|m| format!("{}{}", m.name, CONFIRM_SUFFIX))
+                                .collect::<Vec<_>>();
+            let members = iface.methods
+                               .into_iter()
+                               .map(|m| m.name)
+                               .chain(iface.properties.into_iter().map(|p| p.name))
+                               .chain(iface.signals.into_iter().map(|s| s.name))
+                               .filter(|name| valid_member(name) && self.filter.allows(Scope::Member, name));
+            Ok(all.into_iter()
+                  .chain(members)
+                  .chain(confirms)
+                  .chain(iface.annotations.into_keys())
+                    .map(|name| (name, FileType::RegularFile))
+                    .collect())
+          }
+          None => Err(ENOENT),
+        }
+      }
+
+      _ => Err(ENOTDIR),
+    }
+  }
+
+  /// Unique names owning a well-known name hidden by the filter, they are hidden
+  /// too, or the name could be reached through them all the same.
+  fn hidden_owners(&self, bus: usize, names: &[String]) -> HashSet<String> {
+    names.iter()
+         .filter(|name| !name.starts_with(':') && !self.filter.allows(Scope::Dest, name))
+         .filter_map(|name| BusName::new(&**name).ok())
+         .filter_map(|name| self.get_name_owner(bus, &name).ok())
+         .collect()
+  }
+
+  /// Find an entry in a directory and allocate an inode for it, if it has none yet.
+  fn lookup_child(&mut self, parent: u64, name: &str) -> Result<u64, c_int> {
+    if let Some(entry) = self.nodes.find(parent, name) {
+      return Ok(entry.attr.ino);
+    }
+
+    let ino = self.nodes.next_inode();
+
+    // Filtered out names can't be looked up, no more than they are listed.
+    if let Some(bus) = self.names_dir(parent) {
+      if !self.filter.allows(Scope::Dest, name) {
+        return Err(ENOENT);
+      }
+      if name.starts_with(':') {
+        let names = self.list_names(bus).map_err(|e| self.errno(&e))?;
+        if self.hidden_owners(bus, &names).contains(name) {
+          return Err(ENOENT);
+        }
+      }
+      let dest = match BusName::new(name) {
+        Ok(dest) => dest,
+        Err(_) => return Err(ENOENT),
+      };
+      let root = dbus::Path::new("/").unwrap();
+
+      let attr = self.object_attr(ino, bus, &dest, &root)?;
+      return Ok(self.nodes.insert(parent, name, Node::new(NodeKind::Destination, bus, dest, root), attr).attr.ino);
+    }
+
+    if parent == 1 {
+      return match self.buses.iter().position(|(bus, _)| bus == name) {
+        Some(bus) => {
+          let node = Node::new(NodeKind::Bus,
+                               bus,
+                               BusName::new(DBUS_INSPECT_DEST).unwrap(),
+                               dbus::Path::new("/").unwrap());
+          let attr = self.file_attr_dir(ino);
+          Ok(self.nodes.insert(parent, name, node, attr).attr.ino)
+        }
+        None => Err(ENOENT),
+      };
+    }
+
+    let parent_node = match self.node_by_inode(parent) {
+      Some(node) => node.clone(),
+      None => return Err(ENOENT),
+    };
+
+    let node_info = match self.introspect(parent_node.bus, parent_node.dest.clone(), parent_node.path.clone()) {
+      Ok(Some(info)) => info,
+      Ok(None) => return Err(ENOENT),
+      Err(ref e) => return Err(self.errno(e)),
+    };
+
+    let (node, attr) = match parent_node.kind {
+      NodeKind::Destination | NodeKind::ObjectPath => {
+        if node_info.nodes.iter().any(|n| n.name == name) {
+          // directory with given name, kind is ObjectPath
+          let child_object = match child_path(&parent_node.path, name) {
+            Some(ref o) if !self.filter.allows(Scope::Path, o) => return Err(ENOENT),
+            Some(o) => o,
+            None => return Err(ENOENT),
+          };
+          let attr = self.object_attr(ino, parent_node.bus, &parent_node.dest, &child_object)?;
+          (Node::new(NodeKind::ObjectPath, parent_node.bus, parent_node.dest, child_object), attr)
+        } else if node_info.interfaces.iter().any(|i| i.name == name) && self.filter.allows(Scope::Iface, name) {
+          // directory with given name, kind is Interface
+          let mut node = Node::new(NodeKind::Interface, parent_node.bus, parent_node.dest, parent_node.path);
+          node.iface = dbus::strings::Interface::new(name).ok();
+          (node, self.file_attr_dir(ino))
+        } else {
+          return Err(ENOENT);
+        }
+      }
+
+      NodeKind::Interface => {
+        let iface_info = match node_info.interfaces
+                                        .into_iter()
+                                        .find(|i| Some(&*i.name) == parent_node.iface.as_deref()) {
+          Some(i) => i,
+          None => return Err(ENOENT),
+        };
+
+        if let Some(member) = name.strip_suffix(CONFIRM_SUFFIX) {
+          if valid_member(member) && self.filter.allows(Scope::Member, member) && self.guard.guards(&iface_info.name, member) &&
+             iface_info.methods.iter().any(|m| m.name == member) {
+            // file confirming a call, kind is Confirm
+            let node = parent_node.child(NodeKind::Confirm, member);
+            let attr = self.file_attr_file(ino);
+            return Ok(self.nodes.insert(parent, name, node, attr).attr.ino);
+          }
+          return Err(ENOENT);
+        }
+
+        // `Prop.json` is `Prop` read as JSON, the suffixed names are not listed.
+        let (member, format) = Format::split_suffix(name);
+        let shown = valid_member(member) && self.filter.allows(Scope::Member, member);
+        let child = |kind| {
+          let mut node = parent_node.child(kind, member);
+          node.format = format;
+          node
+        };
+
+        if shown && iface_info.methods.iter().any(|m| m.name == member) {
+          // file with given name, kind is Method
+          let mut attr = self.file_attr_file(ino);
+          if self.read_only() {
+            attr.perm &= 0o444;
+          }
+          (child(NodeKind::Method), attr)
+        } else if let Some(prop) = iface_info.properties.iter().find(|p| shown && p.name == member) {
+          // file with given name, kind is Property
+          let node = child(NodeKind::Property);
+          let attr = self.property_attr(ino, &node, prop);
+          (node, attr)
+        } else if member == PROPERTIES_FILE && iface_info.properties.iter().any(|p| self.filter.allows(Scope::Member, &p.name)) {
+          // file with all properties, kind is Properties
+          let node = child(NodeKind::Properties);
+          let attr = self.properties_attr(ino, &node);
+          (node, attr)
+        } else if shown && iface_info.signals.iter().any(|s| s.name == member) {
+          // file with given name, kind is Signal
+          let mut attr = self.file_attr_file(ino);
+          attr.perm = 0o444;
+          (child(NodeKind::Signal), attr)
+        } else if iface_info.annotations.contains_key(name) {
+          // file with given name, kind is Annotation
+          let node = parent_node.child(NodeKind::Annotation, name);
+          let attr = self.annotation_attr(ino, &node);
+          (node, attr)
+        } else {
+          return Err(ENOENT);
+        }
+      }
+
+      _ => return Err(ENOENT),
+    };
+
+    Ok(self.nodes.insert(parent, name, node, attr).attr.ino)
+  }
+
+  /// Introspection data of an interface, ENOENT if it's gone and the errno of the
+  /// error if introspection failed.
+  fn find_interface(&self, bus: usize, dest: &BusName, object: &dbus::Path, iface: &str) -> Result<Interface, c_int> {
+    match self.introspect(bus, dest.clone(), object.clone()) {
+      Ok(Some(node_info)) => node_info.interfaces.into_iter().find(|i| i.name == iface).ok_or(ENOENT),
+      Ok(None) => Err(ENOENT),
+      Err(ref e) => Err(self.errno(e)),
+    }
+  }
+
+  fn find_property(&self, node: &Node) -> Result<Property, c_int> {
+    match *node {
+      Node { kind: NodeKind::Property, bus, ref dest, ref path, iface: Some(ref iface), member: Some(ref member), .. } => {
+        self.find_interface(bus, dest, path, iface).and_then(|i| i.properties.into_iter().find(|p| p.name == *member).ok_or(ENOENT))
+      }
+      _ => Err(ENOENT),
+    }
+  }
+
+  fn find_method(&self, node: &Node) -> Result<Method, c_int> {
+    match *node {
+      Node { kind: NodeKind::Method, bus, ref dest, ref path, iface: Some(ref iface), member: Some(ref member), .. } => {
+        self.find_interface(bus, dest, path, iface).and_then(|i| i.methods.into_iter().find(|m| m.name == *member).ok_or(ENOENT))
+      }
+      _ => Err(ENOENT),
+    }
+  }
+
+  /// Value of an interface annotation, ending in a newline like every other file.
+  fn read_annotation(&self, node: &Node) -> Result<Vec<u8>, c_int> {
+    match *node {
+      Node { kind: NodeKind::Annotation, bus, ref dest, ref path, iface: Some(ref iface), member: Some(ref member), .. } => {
+        let iface = self.find_interface(bus, dest, path, iface)?;
+        let mut value = iface.annotations.get(member).ok_or(ENOENT)?.clone().into_bytes();
+        value.push(b'\n');
+        Ok(value)
+      }
+      _ => Err(ENOENT),
+    }
+  }
+
+  fn find_signal(&self, node: &Node) -> Result<Signal, c_int> {
+    match *node {
+      Node { kind: NodeKind::Signal, bus, ref dest, ref path, iface: Some(ref iface), member: Some(ref member), .. } => {
+        self.find_interface(bus, dest, path, iface).and_then(|i| i.signals.into_iter().find(|s| s.name == *member).ok_or(ENOENT))
+      }
+      _ => Err(ENOENT),
+    }
+  }
+
+  /// Extended attributes of a file, with the last D-Bus error on it.
+  fn xattrs(&self, ino: u64) -> Result<xattr::Attrs, c_int> {
+    let mut attrs = self.node_xattrs(ino)?;
+    if let Some(err) = self.errors.get(&ino) {
+      xattr::last_error(&mut attrs, err);
+    }
+    // Failing to introspect here would only replace the error worth seeing.
+    self.last_error.borrow_mut().take();
+    Ok(attrs)
+  }
+
+  /// Extended attributes of a node, with the introspection data of the member it stands for.
+  fn node_xattrs(&self, ino: u64) -> Result<xattr::Attrs, c_int> {
+    if ino == 1 {
+      return Ok(Vec::new());
+    }
+
+    let node = match self.node_by_inode(ino) {
+      Some(node) => node,
+      None => return Err(ENOENT),
+    };
+
+    let mut attrs = xattr::kind(node.kind);
+    match node.kind {
+      NodeKind::Interface => {
+        if let Some(ref iface) = node.iface {
+          if let Ok(iface) = self.find_interface(node.bus, &node.dest, &node.path, iface) {
+            xattr::interface(&mut attrs, &iface);
+          }
+        }
+      }
+      NodeKind::Property => {
+        if let Ok(prop) = self.find_property(node) {
+          xattr::property(&mut attrs, &prop);
+        }
+      }
+      NodeKind::Method => {
+        if let Ok(method) = self.find_method(node) {
+          xattr::method(&mut attrs, &method);
+        }
+        if let Some((key, iface, member)) = method_key(node) {
+          xattr::timeout(&mut attrs, self.timeouts.for_call(&key, &node.dest));
+          if self.guard.guards(&iface, &member) {
+            xattr::guarded(&mut attrs, &member);
+          }
+        }
+      }
+      NodeKind::Signal => {
+        if let Ok(signal) = self.find_signal(node) {
+          xattr::signal(&mut attrs, &signal);
+        }
+      }
+      _ => (),
+    }
+    Ok(attrs)
   }
 
-  fn list_names(&self) -> Result<Vec<String>, dbus::Error> {
+  fn list_names(&self, bus: usize) -> Result<Vec<String>, dbus::Error> {
     let msg = Message::new_method_call(DBUS_INSPECT_DEST, DBUS_INSPECT_PATH, DBUS_INSPECT_IFACE, "ListNames").unwrap();
-    self.dbus.send_with_reply_and_block(msg, 1000).map(|msg| {
+    self.call(bus, msg, self.timeouts.for_dest(DBUS_INSPECT_DEST)).map(|msg| {
       match msg.get_items().into_iter().next() {
-        Some(MessageItem::Array(items, _)) => {
-          items.into_iter()
+        Some(MessageItem::Array(items)) => {
+          items.into_vec()
+               .into_iter()
                .filter_map(|s| {
                  match s {
                    MessageItem::Str(s) => Some(s),
@@ -192,11 +756,11 @@ impl DbusFs {
     })
   }
 
-  fn get_connection_unix_user(&self, name: &dbus::BusName) -> Result<u32, dbus::Error> {
+  fn get_connection_unix_user(&self, bus: usize, name: &BusName) -> Result<u32, dbus::Error> {
     let msg = Message::new_method_call(DBUS_INSPECT_DEST, DBUS_INSPECT_PATH, DBUS_INSPECT_IFACE, "GetConnectionUnixUser")
       .unwrap()
-      .append(&**name);
-    self.dbus.send_with_reply_and_block(msg, 1000).map(|msg| {
+      .append1(&**name);
+    self.call(bus, msg, self.timeouts.for_dest(DBUS_INSPECT_DEST)).map(|msg| {
       match msg.get_items().into_iter().next() {
         Some(MessageItem::UInt32(uid)) => uid,
         _ => 0,
@@ -204,192 +768,1363 @@ impl DbusFs {
     })
   }
 
-  fn introspect(&self, dest: dbus::BusName, object: dbus::Path) -> Result<Option<NodeInfo>, dbus::Error> {
-    let msg = Message::new_method_call(dest, object, DBUS_INTROSPECT_IFACE, "Introspect").unwrap();
+  fn get_name_owner(&self, bus: usize, name: &BusName) -> Result<String, dbus::Error> {
+    let msg = Message::new_method_call(DBUS_INSPECT_DEST, DBUS_INSPECT_PATH, DBUS_INSPECT_IFACE, "GetNameOwner")
+      .unwrap()
+      .append1(&**name);
+    self.call(bus, msg, self.timeouts.for_dest(DBUS_INSPECT_DEST)).map(|msg| {
+      match msg.get_items().into_iter().next() {
+        Some(MessageItem::Str(owner)) => owner,
+        _ => (**name).to_owned(),
+      }
+    })
+  }
+
+  fn introspect(&self, bus: usize, dest: BusName, object: dbus::Path) -> Result<Option<NodeInfo>, dbus::Error> {
+    if let Some(info) = self.cache.get(bus, &dest, &object) {
+      return Ok(Some(info));
+    }
+
+    let key = ((*dest).to_owned(), (*object).to_owned());
+    let timeout = self.timeouts.for_dest(&dest);
+    let msg = Message::new_method_call(dest.clone(), object, DBUS_INTROSPECT_IFACE, "Introspect").unwrap();
 
-    self.dbus.send_with_reply_and_block(msg, 1000).map(|msg| {
+    let info = self.call(bus, msg, timeout).map(|msg| {
       match msg.get_items().into_iter().next() {
-        Some(MessageItem::Str(s)) => s.parse().ok(),
+        Some(MessageItem::Str(s)) => s.parse::<NodeInfo>().ok(),
         _ => None,
       }
-    })
+    })?;
+    if let Some(ref info) = info {
+      self.cache.insert(bus, &key.0, &key.1, info);
+    }
+    // Object managers are told from one another by their unique names.
+    if self.cache.owner(bus, &dest).is_none() {
+      if let Ok(owner) = self.get_name_owner(bus, &dest) {
+        self.cache.insert_owner(bus, &dest, &owner);
+      }
+    }
+    Ok(info)
   }
-}
 
-const TTL: Timespec = Timespec { sec: 10, nsec: 0 };
-const CREATE_TIME: Timespec = Timespec {
-  sec: 1381237736,
-  nsec: 0,
-};
+  fn get_property(&self, bus: usize, dest: BusName, object: dbus::Path, iface: &str, name: &str) -> Result<Option<MessageItem>, dbus::Error> {
+    let timeout = self.timeouts.for_dest(&dest);
+    let msg = Message::new_method_call(dest, object, DBUS_PROPERTIES_IFACE, "Get").unwrap().append2(iface, name);
 
-fn split_path<P: AsRef<Path>>(path: P) -> Option<(dbus::BusName, dbus::Path)> {
-  let path: &Path = path.as_ref();
-  let mut iter = path.iter();
-  let dest = iter.next().and_then(|c| c.to_str()).and_then(|d| dbus::BusName::new(d).ok());
-  let obj = iter.as_path().to_str().and_then(|s| dbus::Path::new("/".to_owned() + s).ok());
+    self.call(bus, msg, timeout).map(|msg| {
+      match msg.get_items().into_iter().next() {
+        Some(MessageItem::Variant(value)) => Some(*value),
+        value => value,
+      }
+    })
+  }
 
-  match (dest, obj) {
-    (Some(dest), Some(obj)) => Some((dest, obj)),
-    _ => None,
+  /// A value cached for the file is used as it is, the property is only asked
+  /// for without one.
+  fn read_property(&self, ino: u64, node: &Node, prop: &Property) -> Result<Option<Vec<u8>>, dbus::Error> {
+    match *node {
+      Node { kind: NodeKind::Property, bus, ref dest, ref path, iface: Some(ref iface), .. } => {
+        let format = self.format_of(node);
+        let watch = self.properties.get(&ino);
+        if let Some(value) = watch.and_then(|watch| watch.value()) {
+          return Ok(Some(format.value(&prop.name, &value)));
+        }
+        self.get_property(bus, dest.clone(), path.clone(), iface, &prop.name).map(|value| {
+          value.map(|v| {
+            if let Some(watch) = watch {
+              watch.keep(&v);
+            }
+            format.value(&prop.name, &v)
+          })
+        })
+      }
+      _ => Ok(None),
+    }
   }
-}
 
-#[inline]
-fn list_dot_dirs(ino: u64, offset: u64, reply: &mut ReplyDirectory) -> bool {
-  if offset == 0 {
-    reply.add(ino, 0, FileType::Directory, ".") ||
-      reply.add(ino, 1, FileType::Directory, "..")
-  } else {
-    false
+  fn get_all_properties(&self, bus: usize, dest: BusName, object: dbus::Path, iface: &str) -> Result<Option<MessageItem>, dbus::Error> {
+    let timeout = self.timeouts.for_dest(&dest);
+    let msg = Message::new_method_call(dest, object, DBUS_PROPERTIES_IFACE, "GetAll").unwrap().append1(iface);
+
+    self.call(bus, msg, timeout).map(|msg| msg.get_items().into_iter().next())
   }
-}
 
+  fn read_properties(&self, node: &Node) -> Result<Option<Vec<u8>>, dbus::Error> {
+    match *node {
+      Node { kind: NodeKind::Properties, bus, ref dest, ref path, iface: Some(ref iface), .. } => {
+        let format = self.format_of(node);
+        self.get_all_properties(bus, dest.clone(), path.clone(), iface)
+            .map(|value| value.map(|v| format.properties(&self.filter_properties(v))))
+      }
+      _ => Ok(None),
+    }
+  }
 
-impl Filesystem for DbusFs {
-  fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
-    match ino {
-      1 => reply.attr(&TTL, &ROOT_DIR),
-      ino => {
-        match self.attr_by_inode(ino) {
-          Some(attr) => reply.attr(&TTL, attr),
-          None => reply.error(ENOENT),
-        }
+  /// Leave the properties hidden from the interface directory out of a `GetAll` reply.
+  fn filter_properties(&self, value: MessageItem) -> MessageItem {
+    match value {
+      MessageItem::Dict(entries) => {
+        // Dict signatures are `a{KV}` with a single character key, dropping entries keeps them.
+        let sig = entries.signature().to_string();
+        let (key, value) = (sig[2..3].to_owned(), sig[3..sig.len() - 1].to_owned());
+        let entries = entries.into_vec()
+                             .into_iter()
+                             .filter(|entry| {
+                               match *entry {
+                                 (MessageItem::Str(ref name), _) => self.filter.allows(Scope::Member, name),
+                                 _ => true,
+                               }
+                             })
+                             .collect();
+        MessageItem::Dict(MessageItemDict::new(entries, key.into(), value.into()).unwrap())
       }
+      value => value,
     }
   }
 
-  fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: u64, mut reply: ReplyDirectory) {
-    match ino {
-      1 => {
-        if list_dot_dirs(ino, offset, &mut reply) {
-          return reply.ok();
-        }
+  #[cfg(not(feature = "readonly"))]
+  fn set_property(&self, bus: usize, dest: BusName, object: dbus::Path, iface: &str, name: &str, value: MessageItem) -> Result<(), dbus::Error> {
+    let timeout = self.timeouts.for_dest(&dest);
+    let msg = Message::new_method_call(dest, object, DBUS_PROPERTIES_IFACE, "Set")
+      .unwrap()
+      .append3(iface, name, MessageItem::Variant(Box::new(value)));
 
-        match self.list_names() {
-          Ok(items) => {
-            for (no, name) in items.into_iter().skip(offset as usize).enumerate() {
-              if let Some(attr) = self.make_inode(&*name) {
-                if reply.add(attr.ino, offset + (no + 2) as u64, attr.kind, &*name) {
-                  break;
-                }
-              }
-            }
-            reply.ok();
-          }
-          Err(_) => reply.error(ENOENT),
-        }
+    self.call(bus, msg, timeout).map(|_| ())
+  }
+
+  #[cfg(feature = "readonly")]
+  fn write_property(&self, _node: &Node, _buffer: &[u8]) -> Result<(), c_int> {
+    Err(EROFS)
+  }
+
+  #[cfg(not(feature = "readonly"))]
+  fn write_property(&self, node: &Node, buffer: &[u8]) -> Result<(), c_int> {
+    let prop = self.find_property(node)?;
+    let text = str::from_utf8(buffer).map_err(|_| EINVAL)?;
+    let value = value::parse(text, &prop.typesig).map_err(|_| EINVAL)?;
+
+    match *node {
+      Node { kind: NodeKind::Property, bus, ref dest, ref path, iface: Some(ref iface), .. } => {
+        self.set_property(bus, dest.clone(), path.clone(), iface, &prop.name, value).map_err(|e| self.errno(&e))
       }
+      _ => Err(ENOENT),
+    }
+  }
 
-      ino => {
-        let parent = match self.path_by_inode(ino) {
-          Some(p) => p.to_owned(),
-          None => return reply.error(ENOENT),
-        };
+  #[cfg(feature = "readonly")]
+  fn call_method(&self, _node: &Node, _input: &[u8]) -> Result<Vec<u8>, c_int> {
+    Err(EROFS)
+  }
 
-        let (dest, object) = match split_path(&parent) {
-          Some((d, o)) => (d, o),
-          None => return reply.error(ENOENT),
-        };
+  #[cfg(not(feature = "readonly"))]
+  fn call_method(&self, node: &Node, input: &[u8]) -> Result<Vec<u8>, c_int> {
+    let method = self.find_method(node)?;
+    let sigs = |dir: Direction| {
+      method.args.iter().filter(|&(_, d)| *d == dir).map(|(arg, _)| &arg.typesig).collect::<Vec<_>>()
+    };
+    let names = method.args
+                      .iter()
+                      .filter(|&(_, d)| *d == Direction::Out)
+                      .map(|(arg, _)| &*arg.name)
+                      .collect::<Vec<_>>();
+    let format = self.format_of(node);
 
-        match self.introspect(dest, object) {
-          Ok(Some(ni)) => {
-            if !list_dot_dirs(ino, offset, &mut reply) {
-              if ni.nodes.is_empty() {
-              } else {
-                for (no, node) in ni.nodes.iter().skip(offset as usize).enumerate() {
-                  let path = parent.join(&*node.name);
-                  if let Some(attr) = self.make_inode(path) {
-                    if reply.add(attr.ino, offset + (no + 2) as u64, attr.kind, &*node.name) {
-                      break;
-                    }
-                  }
-                }
-              }
-            }
-            reply.ok();
-          },
+    let text = str::from_utf8(input).map_err(|_| EINVAL)?;
+    let items = value::parse_args(text, &sigs(Direction::In)).map_err(|_| EINVAL)?;
 
-          Err(ref e) if e.name() == Some(DBUS_ACCESS_ERROR) => {
-            reply.error(EACCES);
-          },
-          _ => {
-            reply.error(ENOENT);
-          },
-        }
+    match *node {
+      Node { kind: NodeKind::Method, bus, ref dest, ref path, iface: Some(ref iface), member: Some(ref member), .. } => {
+        let timeout = method_key(node).map_or_else(|| self.timeouts.for_dest(dest), |(key, _, _)| self.timeouts.for_call(&key, dest));
+        let member = Member::new(&**member).map_err(|_| ENOENT)?;
+        let mut msg = Message::new_method_call(dest.clone(), path.clone(), iface.clone(), member).map_err(|_| ENOENT)?;
+        msg.append_items(&items);
+
+        self.call(bus, msg, timeout)
+            .map(|reply| format.reply(&names, &reply.get_items()))
+            .map_err(|e| self.errno(&e))
       }
+      _ => Err(ENOENT),
     }
   }
 
-  fn lookup(&mut self, _req: &Request, parent: u64, name: &Path, reply: ReplyEntry) {
-    println!("lookup: ({}, {})", parent, name.display());
+  /// Make the call pending on a method handle, unless it has been made already.
+  fn complete_call(&mut self, ino: u64, fh: u64) {
+    let input = match self.handles.get(&fh) {
+      Some(&Handle::Method { ref input, written: true, output: None, .. }) => input.clone(),
+      _ => return,
+    };
 
-    if let Some(attr) = self.inodes.get((parent, name)).and_then(|ino| self.inode_attr.get(ino)) {
-      return reply.attr(&TTL, attr, 0);
+    let node = match self.node_by_inode(ino) {
+      Some(node) => node.clone(),
+      None => return,
+    };
+    let result = match method_key(&node) {
+      Some((ref key, ref iface, ref member)) if !self.guard.permit(key, iface, member) => {
+        // Kept as the error of the file, stderr is gone once daemonized.
+        *self.last_error.borrow_mut() = Some(format!("refusing to call guarded {}.{}, write the token from {}{} back to it \
+                                                      first, or mount with --allow-guarded",
+                                                     iface,
+                                                     member,
+                                                     member,
+                                                     CONFIRM_SUFFIX));
+        Err(EPERM)
+      }
+      _ => self.call_method(&node, &input),
+    };
+    if let Err(errno) = result {
+      self.keep_error(ino, errno);
     }
 
-    if let Some(parent) = self.inode_name(parent) {
-      let node_info = match self.introspect(parent.1, parent.2) {
-        Ok(Some(info)) => info,
-        _ => return reply.error(ENOENT),
-      };
+    if let Some(&mut Handle::Method { ref mut output, ref mut position, .. }) = self.handles.get_mut(&fh) {
+      *output = Some(result);
+      *position = 0;
+    }
+  }
 
-      let (attr, name) = match parent.0 {
-        NodeKind::Destination => if node_info.nodes.find(|&n| n.name == name).is_some() {
-          // directory with given name, kind is ObjectPath
-          (file_attr_dir(self.last_inode.fetch_add(1, Ordering::SeqCst)), (NodeKind::ObjectPath, parent.1, parent.2.join(name), None, None))
-        },
-        NodeKind::ObjectPath => if node_info.nodes.find(|&n| n.name == name).is_some() {
-          // directory with given name, kind is ObjectPath
-          (file_attr_dir(self.last_inode.fetch_add(1, Ordering::SeqCst)), (NodeKind::ObjectPath, parent.1, parent.2.join(name), None, None))
-        } else if let Some(iface) = node_info.interfaces.find(|&i| i.name == name) {
-          // directory with given name, kind is Interface
-          (file_attr_dir(self.last_inode.fetch_add(1, Ordering::SeqCst)), (NodeKind::Interface, parent.1, parent.2, Some(name), None))
-        },
-        NodeKind::Interface => if let Some(iface) = node_info.interfaces.find(|&n| n.name == parent.3) {
-          // file with given name
-          if let Some(method) = iface.methods.find(|&n| n.name == name) {
-            // kind is Method
-            (file_attr_file(self.last_inode.fetch_add(1, Ordering::SeqCst)), (NodeKind::Method, parent.1, parent.2, parent.3, Some(name)))
-          } else if let Some(prop) = iface.properties.find(|&n| n.name == name) {
-            // kind is Property
-            (file_attr_file(self.last_inode.fetch_add(1, Ordering::SeqCst)), (NodeKind::Property, parent.1, parent.2, parent.3, Some(name)))
-          } else if let Some(signal) = iface.signals.find(|&n| n.name == name) {
-            // kind is Signal
-            (file_attr_file(self.last_inode.fetch_add(1, Ordering::SeqCst)), (NodeKind::Signal, parent.1, parent.2, parent.3, Some(name)))
-          } else if let Some(anno) = iface.annotations.get(name) {
-            // kind is Annotation
-            (file_attr_file(self.last_inode.fetch_add(1, Ordering::SeqCst)), (NodeKind::Annotation, parent.1, parent.2, parent.3, Some(name)))
-          }
-        },
-        _ => return reply.error(ENOENT),
-      };
+  fn watch_signal(&mut self, node: &Node) -> Result<SignalWatch, c_int> {
+    let signal = self.find_signal(node)?;
 
-      let ino = attr.ino;
-      reply.entry(&TTL, &attr, 0);
-      self.inode_name.insert(ino, name);
-      self.inode_attr.insert(ino, attr);
+    let watch = match *node {
+      Node { kind: NodeKind::Signal, bus, ref dest, ref path, iface: Some(ref iface), .. } => {
+        let owner = self.get_name_owner(bus, dest).ok();
+        SignalWatch::new(dest, owner, path, iface, &signal, self.format_of(node))
+      }
+      _ => return Err(ENOENT),
+    };
 
-    } else {
-      return reply.error(ENOENT);
+    self.add_match(node.bus, &watch.rule).map_err(|e| self.errno(&e))?;
+    Ok(watch)
+  }
+
+  /// Cache the value of a looked up property file as its `EmitsChangedSignal`
+  /// annotation allows, following `PropertiesChanged` unless it's constant. Without
+  /// the match rule the value is just asked for on every read, as with `false`.
+  fn watch_property(&mut self, ino: u64) {
+    if self.properties.contains_key(&ino) {
+      return;
+    }
+    let watch = match self.node_by_inode(ino) {
+      Some(&Node { kind: NodeKind::Property, bus, ref dest, ref path, iface: Some(ref iface), member: Some(ref member), .. }) => {
+        let emits = self.find_interface(bus, dest, path, iface)
+                        .ok()
+                        .and_then(|i| i.properties.iter().find(|p| p.name == *member).map(|p| i.emits_changed(p)));
+        // Not finding it again is as good as an error here.
+        self.last_error.borrow_mut().take();
+        let owner = match emits {
+          Some(EmitsChanged::True) | Some(EmitsChanged::Invalidates) => self.get_name_owner(bus, dest).ok(),
+          _ => None,
+        };
+        match emits.and_then(|emits| PropertyWatch::new(dest, owner, path, iface, member, emits)) {
+          Some(watch) => (bus, watch),
+          None => return,
+        }
+      }
+      _ => return,
+    };
+
+    if let Some(ref rule) = watch.1.rule {
+      if self.add_match(watch.0, rule).is_err() {
+        return;
+      }
     }
+    self.properties.insert(ino, watch.1);
   }
 
-  fn read(&mut self, _req: &Request, ino: u64, _fh: u64, offset: u64, _size: u32, reply: ReplyData) {
-    match ino {
-      1 => reply.error(ENOENT),
-      // ino => {
-      // match self.name_by_inode(ino).and_then(split_path).and_then(|(d, o)| self.introspect(d, o).ok()) {
-      // Some(Some(data)) => reply.data(&data.as_bytes()[offset as usize..]),
-      _ => reply.error(ENOENT),
-      // }
-      // }
+  /// Stop following a property file once the kernel forgets it.
+  fn unwatch_property(&mut self, ino: u64, bus: usize) {
+    if let Some(watch) = self.properties.remove(&ino) {
+      if let Some(ref rule) = watch.rule {
+        self.remove_match(bus, rule);
+      }
     }
   }
 
-}
+  /// Install a match rule, unless another open file has already done it.
+  fn add_match(&mut self, bus: usize, rule: &str) -> Result<(), dbus::Error> {
+    let key = (bus, rule.to_owned());
+    if !self.matches.contains_key(&key) {
+      self.match_rule(bus, "AddMatch", rule)?;
+    }
+    *self.matches.entry(key).or_insert(0) += 1;
+    Ok(())
+  }
 
-fn main() {
-  let mountpoint = env::args().nth(1).unwrap();
-  let conn = DbusFs::new(BusType::System).unwrap();
-  fuse::mount(conn, &mountpoint, &[]);
+  /// Remove a match rule once the last file using it is closed.
+  fn remove_match(&mut self, bus: usize, rule: &str) {
+    let key = (bus, rule.to_owned());
+    let last = match self.matches.get_mut(&key) {
+      Some(count) => {
+        *count -= 1;
+        *count == 0
+      }
+      None => false,
+    };
+
+    if last {
+      self.matches.remove(&key);
+      let _ = self.match_rule(bus, "RemoveMatch", rule);
+    }
+  }
+
+  /// Call `AddMatch` or `RemoveMatch` on the bus daemon.
+  fn match_rule(&self, bus: usize, method: &str, rule: &str) -> Result<(), dbus::Error> {
+    let msg = Message::new_method_call(DBUS_INSPECT_DEST, DBUS_INSPECT_PATH, DBUS_INSPECT_IFACE, method).unwrap().append1(rule);
+    self.call(bus, msg, self.timeouts.for_dest(DBUS_INSPECT_DEST)).map(|_| ())
+  }
+
+  /// Take the signals received meanwhile and queue them on the matching signal files,
+  /// handing them to the reads waiting there.
+  fn receive_signals(&mut self) {
+    for bus in 0..self.buses.len() {
+      loop {
+        let msg = match self.conn(bus).blocking_pop_message(Duration::from_millis(0)) {
+          Ok(Some(msg)) => msg,
+          Ok(None) | Err(_) => break,
+        };
+
+        // Nothing is served here, calls to us get the error any unknown method gets.
+        if msg.msg_type() == MessageType::MethodCall {
+          if let Some(reply) = channel::default_reply(&msg) {
+            let _ = self.conn(bus).send(reply);
+          }
+        }
+        if msg.msg_type() != MessageType::Signal {
+          continue;
+        }
+
+        let change = Change::from_signal(&msg);
+        if let Some(ref change) = change {
+          self.forget_stale(bus, change);
+        }
+        let mut changed = Vec::new();
+        for (&ino, watch) in &mut self.properties {
+          let node = match self.nodes.get(ino) {
+            Some(entry) if entry.node.bus == bus => &entry.node,
+            _ => continue,
+          };
+          // A new owner is yet to say anything, the value has to be asked for again.
+          let owner = self.cache.owner(bus, &node.dest);
+          if change.as_ref().is_some_and(|change| change.stales_node(&node.dest, owner.as_deref(), &node.path)) {
+            watch.forget();
+          }
+          if let Some(Change::Owner(ref name, ref owner)) = change {
+            watch.owner_changed(name, owner);
+          }
+          if watch.update(&msg) {
+            changed.push(ino);
+          }
+        }
+        for ino in changed {
+          self.property_changed(ino);
+          self.notify_changed(ino);
+        }
+        for handle in self.handles.values_mut() {
+          if let Handle::Signal { bus: watched, ref mut watch, ref mut queue, ref mut waiting, ref mut poll } = *handle {
+            if watched != bus {
+              continue;
+            }
+            if let Some(Change::Owner(ref name, ref owner)) = change {
+              watch.owner_changed(name, owner);
+            }
+            if watch.matches(&msg) {
+              queue.push_back(watch.format(&msg));
+              while !queue.is_empty() {
+                match waiting.pop_front() {
+                  Some((_, size, reply)) => reply.data(&next_line(queue, size)),
+                  None => break,
+                }
+              }
+              if !queue.is_empty() {
+                wake(poll);
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+
+  /// Drop what a change made stale: cached introspection data, and the files the kernel
+  /// holds for the objects, to be looked up again. A name coming or going changes the
+  /// directory listing names too.
+  fn forget_stale(&mut self, bus: usize, change: &Change) {
+    self.cache.forget(bus, change);
+    let cache = &self.cache;
+    let held = self.nodes.unlink(|node| {
+      node.bus == bus && change.stales_node(&node.dest, cache.owner(bus, &node.dest).as_deref(), &node.path)
+    });
+    self.notices.extend(held.into_iter().map(|(parent, name)| Notice::Entry(parent, name)));
+
+    if let Change::Owner(..) = *change {
+      let dir = if self.buses.len() == 1 {
+        Some(1)
+      } else {
+        self.nodes.find(1, &self.buses[bus].0).map(|entry| entry.attr.ino)
+      };
+      if let Some(ino) = dir {
+        self.notify_changed(ino);
+      }
+    }
+  }
+
+  /// Make the handles watching a property file readable again.
+  fn property_changed(&mut self, ino: u64) {
+    for handle in self.handles.values_mut() {
+      if let Handle::Property { ino: watched, watching: true, ref mut changed, ref mut poll, .. } = *handle {
+        if watched == ino {
+          *changed = true;
+          wake(poll);
+        }
+      }
+    }
+  }
+
+  /// Have the kernel drop what it knows of an inode and inotify watchers hear of it.
+  fn notify_changed(&mut self, ino: u64) {
+    if let Some(path) = self.nodes.path(ino) {
+      let path = self.mountpoint.join(path);
+      self.notices.push(Notice::Inode(ino, path));
+    }
+  }
+
+  /// Handle the signals received meanwhile, before looked up files or cached
+  /// introspection data are used.
+  fn catch_up(&mut self) {
+    self.receive_signals();
+  }
+
+  /// Fail the signal reads of threads that got a signal of their own with EINTR.
+  /// The kernel can't interrupt a read sent to the file system, it waits for the
+  /// reply even if the reader is killed.
+  fn interrupt_reads(&mut self) {
+    for handle in self.handles.values_mut() {
+      if let Handle::Signal { ref mut waiting, .. } = *handle {
+        let (interrupted, left): (Vec<_>, Vec<_>) = waiting.drain(..).partition(|&(pid, _, _)| daemon::interrupted(pid));
+        waiting.extend(left);
+        for (_, _, reply) in interrupted {
+          reply.error(EINTR);
+        }
+      }
+    }
+  }
+
+  /// Ask for the signals telling when files and cached introspection data go stale.
+  /// Without them the cache is turned off, rather than kept until it expires.
+  fn watch_changes(&mut self) {
+    for bus in 0..self.buses.len() {
+      for rule in cache::RULES {
+        if let Err(err) = self.add_match(bus, rule) {
+          let _ = writeln!(io::stderr(),
+                           "dbusfs: can't watch for changes, not caching introspection data: {}",
+                           err.message().unwrap_or("unknown error"));
+          self.cache = Cache::new(0);
+          return;
+        }
+      }
+    }
+  }
+
+  fn flush_handle(&mut self, ino: u64, fh: u64) -> Result<(), c_int> {
+    // Arguments written and not called with yet, nobody is going to read the reply.
+    let call = match self.handles.get(&fh) {
+      Some(&Handle::Method { written, output: None, .. }) => written,
+      _ => false,
+    };
+    if call {
+      self.complete_call(ino, fh);
+      return match self.handles.get(&fh) {
+        Some(&Handle::Method { output: Some(Err(errno)), .. }) => Err(errno),
+        _ => Ok(()),
+      };
+    }
+
+    let confirm = match self.handles.get_mut(&fh) {
+      Some(&mut Handle::Confirm { ref key, ref mut input, .. }) if !input.is_empty() => {
+        let token = String::from_utf8_lossy(input).trim().to_owned();
+        input.clear();
+        Some((key.clone(), token))
+      }
+      _ => None,
+    };
+    if let Some((key, token)) = confirm {
+      return if self.guard.confirm(&key, &token) {
+        Ok(())
+      } else {
+        Err(EPERM)
+      };
+    }
+
+    let buffer = match self.handles.get_mut(&fh) {
+      Some(&mut Handle::Property { ref mut buffer, ref mut dirty, .. }) if *dirty => {
+        *dirty = false;
+        buffer.clone()
+      }
+      _ => return Ok(()),
+    };
+
+    // Whatever was cached is outdated now, even if the write failed halfway.
+    if let Some(watch) = self.properties.get(&ino) {
+      watch.forget();
+    }
+    match self.node_by_inode(ino) {
+      Some(node) => self.write_property(node, &buffer),
+      None => Err(ENOENT),
+    }
+  }
+}
+
+/// Bypass the page cache: call files have no meaningful size, and property values
+/// change on the bus while the kernel still holds the old ones and their size.
+const FOPEN_DIRECT_IO: u32 = 1 << 0;
+
+/// What `poll` reports for files that are always ready, as for any file of a file
+/// system that can't poll. Failing it would make the kernel stop asking for all files.
+const DEFAULT_POLLMASK: u32 = (POLLIN | POLLOUT | POLLRDNORM | POLLWRNORM) as u32;
+
+/// Reply with an attribute value or list. A size of zero asks for the size the
+/// caller needs, a smaller one is ERANGE.
+fn reply_xattr(reply: ReplyXattr, data: &[u8], size: u32) {
+  if size == 0 {
+    reply.size(data.len() as u32)
+  } else if data.len() > size as usize {
+    reply.error(ERANGE)
+  } else {
+    reply.data(data)
+  }
+}
+
+/// Wake the poller waiting for a handle to become readable.
+fn wake(poll: &mut Option<PollHandle>) {
+  if let Some(poll) = poll.take() {
+    let _ = poll.notify();
+  }
+}
+
+/// Most text a handle takes in writes, values and arguments are never near this big.
+const MAX_INPUT_SIZE: u64 = 1 << 20;
+
+/// The next line queued on a signal file, or as much of it as fits, leaving the rest.
+fn next_line(queue: &mut VecDeque<Vec<u8>>, size: u32) -> Vec<u8> {
+  let mut line = queue.pop_front().unwrap_or_default();
+  if line.len() > size as usize {
+    let rest = line.split_off(size as usize);
+    queue.push_front(rest);
+  }
+  line
+}
+
+/// Inode number reported for directory entries that have not been looked up yet.
+const FUSE_UNKNOWN_INO: u64 = 0xffffffff;
+
+/// How long the bus thread waits for signals before looking at the file system again,
+/// and at the readers waiting for one.
+const BUS_POLL_MS: c_int = 200;
+
+const BLOCK_SIZE: u32 = 512;
+
+fn create_time() -> SystemTime {
+  UNIX_EPOCH + Duration::from_secs(1381237736)
+}
+
+/// Key telling a method on one object apart from all others, with its interface and name,
+/// for guards and timeouts set on it.
+fn method_key(node: &Node) -> Option<(String, String, String)> {
+  match *node {
+    Node { bus, ref dest, ref path, iface: Some(ref iface), member: Some(ref member), .. } => {
+      Some((format!("{} {} {} {}.{}", bus, &**dest, &**path, &**iface, member), (**iface).to_owned(), member.clone()))
+    }
+    _ => None,
+  }
+}
+
+/// Whether a member name from introspection data can go into a message or a match
+/// rule, members with others are neither listed nor looked up.
+fn valid_member(name: &str) -> bool {
+  Member::new(name).is_ok()
+}
+
+fn child_path(parent: &dbus::Path, name: &str) -> Option<dbus::Path<'static>> {
+  let path = match &**parent {
+    "/" => format!("/{}", name),
+    parent => format!("{}/{}", parent, name),
+  };
+  dbus::Path::new(path).ok()
+}
+
+#[inline]
+fn list_dot_dirs(ino: u64, offset: i64, reply: &mut ReplyDirectory) -> bool {
+  if offset == 0 {
+    reply.add(ino, 1, FileType::Directory, ".") ||
+      reply.add(ino, 2, FileType::Directory, "..")
+  } else {
+    false
+  }
+}
+
+
+impl Filesystem for DbusFs {
+  fn init(&mut self, _req: &Request, _config: &mut KernelConfig) -> Result<(), c_int> {
+    self.watch_changes();
+    self.daemon.ready();
+    Ok(())
+  }
+
+  fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+    self.catch_up();
+    match self.current_attr(ino) {
+      Some(attr) => reply.attr(&self.ttl, &attr),
+      None => reply.error(ENOENT),
+    }
+  }
+
+  fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+    self.catch_up();
+    let entries = match self.list_dir(ino) {
+      Ok(entries) => entries,
+      Err(errno) => return reply.error(self.keep_error(ino, errno)),
+    };
+
+    if list_dot_dirs(ino, offset, &mut reply) {
+      return reply.ok();
+    }
+
+    // Listing a directory allocates no inodes, the kernel only holds on to looked up ones.
+    let skip = cmp::max(offset, 2) - 2;
+    for (no, (name, kind)) in entries.into_iter().skip(skip as usize).enumerate() {
+      let child = self.nodes.find(ino, &name).map_or(FUSE_UNKNOWN_INO, |e| e.attr.ino);
+      if reply.add(child, skip + (no + 3) as i64, kind, &*name) {
+        break;
+      }
+    }
+    reply.ok();
+  }
+
+  fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+    self.catch_up();
+
+    let name = match name.to_str() {
+      Some(name) => name,
+      None => return reply.error(ENOENT),
+    };
+
+    match self.lookup_child(parent, name) {
+      Ok(ino) => {
+        match self.nodes.get(ino) {
+          Some(entry) => reply.entry(&self.ttl, &entry.attr, entry.generation),
+          None => return reply.error(ENOENT),
+        }
+        self.nodes.lookup(ino);
+        self.watch_property(ino);
+      }
+      Err(errno) => reply.error(self.keep_error(parent, errno)),
+    }
+  }
+
+  fn forget(&mut self, _req: &Request, ino: u64, nlookup: u64) {
+    let bus = self.node_by_inode(ino).map(|node| node.bus);
+    self.nodes.forget(ino, nlookup);
+    if self.nodes.get(ino).is_none() {
+      self.errors.remove(&ino);
+      if let Some(bus) = bus {
+        self.unwatch_property(ino, bus);
+      }
+    }
+  }
+
+  fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+    self.catch_up();
+    let attrs = match self.xattrs(ino) {
+      Ok(attrs) => attrs,
+      Err(errno) => return reply.error(errno),
+    };
+
+    match attrs.into_iter().find(|(attr, _)| OsStr::new(attr) == name) {
+      Some((_, value)) => reply_xattr(reply, value.as_bytes(), size),
+      None => reply.error(ENODATA),
+    }
+  }
+
+  /// Names of the attributes, each ending with a zero byte.
+  fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+    self.catch_up();
+    let attrs = match self.xattrs(ino) {
+      Ok(attrs) => attrs,
+      Err(errno) => return reply.error(errno),
+    };
+
+    let mut names = Vec::new();
+    for (name, _) in attrs {
+      names.extend_from_slice(name.as_bytes());
+      names.push(0);
+    }
+    reply_xattr(reply, &names, size)
+  }
+
+  fn setxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, value: &[u8], _flags: i32, _position: u32, reply: ReplyEmpty) {
+    let key = match self.node_by_inode(ino) {
+      Some(node) if node.kind == NodeKind::Method && name == OsStr::new(xattr::TIMEOUT) => method_key(node).map(|(key, _, _)| key),
+      Some(_) => None,
+      None => return reply.error(ENOENT),
+    };
+    let key = match key {
+      Some(key) => key,
+      None => return reply.error(EOPNOTSUPP),
+    };
+
+    match str::from_utf8(value).map_err(|e| e.to_string()).and_then(timeout::parse_ms) {
+      Ok(ms) => {
+        self.timeouts.set_call(&key, ms);
+        reply.ok()
+      }
+      Err(_) => reply.error(EINVAL),
+    }
+  }
+
+  /// Removing a timeout set on a method file brings back the default.
+  fn removexattr(&mut self, _req: &Request, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+    let key = match self.node_by_inode(ino) {
+      Some(node) if node.kind == NodeKind::Method && name == OsStr::new(xattr::TIMEOUT) => method_key(node).map(|(key, _, _)| key),
+      Some(_) => None,
+      None => return reply.error(ENOENT),
+    };
+
+    match key {
+      Some(key) => {
+        self.timeouts.clear_call(&key);
+        reply.ok()
+      }
+      None => reply.error(EOPNOTSUPP),
+    }
+  }
+
+  fn read(&mut self, req: &Request, ino: u64, fh: u64, offset: i64, size: u32, flags: i32, _lock_owner: Option<u64>, reply: ReplyData) {
+    self.catch_up();
+    let node = match self.node_by_inode(ino) {
+      Some(node) => node.clone(),
+      None => return reply.error(ENOENT),
+    };
+
+    match node.kind {
+      NodeKind::Property => {
+        let prop = match self.find_property(&node) {
+          Ok(prop) => prop,
+          Err(errno) => return reply.error(self.keep_error(ino, errno)),
+        };
+
+        if prop.access == Access::Write {
+          return reply.error(EACCES);
+        }
+        // A change from here on is one this read may not have seen.
+        if let Some(&mut Handle::Property { ref mut changed, .. }) = self.handles.get_mut(&fh) {
+          *changed = false;
+        }
+
+        match self.read_property(ino, &node, &prop) {
+          Ok(Some(data)) => {
+            let start = cmp::min(offset as usize, data.len());
+            let end = cmp::min(start + size as usize, data.len());
+            reply.data(&data[start..end])
+          }
+          Ok(None) => reply.error(ENOENT),
+          Err(ref e) => reply.error(self.keep_error(ino, self.errno(e))),
+        }
+      }
+
+      NodeKind::Properties => {
+        match self.read_properties(&node) {
+          Ok(Some(data)) => {
+            let start = cmp::min(offset as usize, data.len());
+            let end = cmp::min(start + size as usize, data.len());
+            reply.data(&data[start..end])
+          }
+          Ok(None) => reply.error(ENOENT),
+          Err(ref e) => reply.error(self.keep_error(ino, self.errno(e))),
+        }
+      }
+
+      // With nothing queued the reply is left to the bus thread, once a signal comes.
+      NodeKind::Signal => {
+        match self.handles.get_mut(&fh) {
+          Some(&mut Handle::Signal { ref mut queue, ref mut waiting, .. }) => {
+            if !queue.is_empty() {
+              reply.data(&next_line(queue, size))
+            } else if flags & O_NONBLOCK != 0 {
+              reply.error(EAGAIN)
+            } else {
+              waiting.push_back((req.pid(), size, reply))
+            }
+          }
+          _ => reply.error(EBADF),
+        }
+      }
+
+      NodeKind::Method => {
+        self.complete_call(ino, fh);
+
+        match self.handles.get_mut(&fh) {
+          Some(&mut Handle::Method { output: Some(Ok(ref data)), ref mut position, .. }) => {
+            let start = cmp::min(*position, data.len());
+            let end = cmp::min(start + size as usize, data.len());
+            *position = end;
+            reply.data(&data[start..end])
+          }
+          Some(&mut Handle::Method { output: Some(Err(errno)), .. }) => reply.error(errno),
+          Some(&mut Handle::Method { output: None, .. }) => reply.error(EINVAL),
+          _ => reply.error(EBADF),
+        }
+      }
+
+      NodeKind::Confirm => {
+        match self.handles.get(&fh) {
+          Some(Handle::Confirm { token, .. }) => {
+            let start = cmp::min(offset as usize, token.len());
+            let end = cmp::min(start + size as usize, token.len());
+            reply.data(&token[start..end])
+          }
+          _ => reply.error(EBADF),
+        }
+      }
+
+      NodeKind::Annotation => {
+        match self.read_annotation(&node) {
+          Ok(data) => {
+            let start = cmp::min(offset as usize, data.len());
+            let end = cmp::min(start + size as usize, data.len());
+            reply.data(&data[start..end])
+          }
+          Err(errno) => reply.error(self.keep_error(ino, errno)),
+        }
+      }
+
+      _ => reply.error(ENOENT),
+    }
+  }
+
+  fn setattr(&mut self, _req: &Request, ino: u64, _mode: Option<u32>, _uid: Option<u32>, _gid: Option<u32>, size: Option<u64>,
+             _atime: Option<TimeOrNow>, _mtime: Option<TimeOrNow>, _ctime: Option<SystemTime>, fh: Option<u64>,
+             _crtime: Option<SystemTime>, _chgtime: Option<SystemTime>, _bkuptime: Option<SystemTime>, _flags: Option<u32>,
+             reply: ReplyAttr) {
+    // Only truncation is meaningful here: `echo value > Prop` truncates before it writes.
+    if let (Some(size), Some(fh)) = (size, fh) {
+      if let Some(&mut Handle::Property { ref mut buffer, .. }) = self.handles.get_mut(&fh) {
+        buffer.truncate(size as usize);
+      }
+    }
+
+    // Times set, as changed files are touched, are replaced with the actual ones.
+    match self.current_attr(ino) {
+      Some(mut attr) => {
+        if let Some(size) = size {
+          attr.size = size;
+        }
+        reply.attr(&self.ttl, &attr)
+      }
+      None => reply.error(ENOENT),
+    }
+  }
+
+  fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+    self.catch_up();
+    let node = match self.node_by_inode(ino) {
+      Some(node) => node.clone(),
+      None => return reply.error(ENOENT),
+    };
+
+    match node.kind {
+      NodeKind::Property => {
+        let writing = flags & O_ACCMODE != O_RDONLY;
+        let prop = match self.find_property(&node) {
+          Ok(prop) => prop,
+          Err(errno) => return reply.error(self.keep_error(ino, errno)),
+        };
+
+        if writing && self.read_only() {
+          return reply.error(EROFS);
+        }
+        if writing && (prop.access == Access::Read || self.format_of(&node) != Format::Text) {
+          return reply.error(EACCES);
+        }
+
+        let fh = self.next_handle();
+        self.handles.insert(fh, Handle::Property {
+          ino,
+          buffer: Vec::new(),
+          dirty: false,
+          watching: !writing && flags & O_NONBLOCK != 0,
+          changed: true,
+          poll: None,
+        });
+        reply.opened(fh, FOPEN_DIRECT_IO)
+      }
+
+      NodeKind::Properties => reply.opened(0, FOPEN_DIRECT_IO),
+
+      // Annotations are fixed by the interface, nothing can be written to them.
+      NodeKind::Annotation if flags & O_ACCMODE != O_RDONLY => reply.error(EACCES),
+      NodeKind::Annotation => reply.opened(0, FOPEN_DIRECT_IO),
+
+      // Any call may change something, even one only read from.
+      NodeKind::Method if self.read_only() => reply.error(EROFS),
+
+      NodeKind::Method => {
+        let fh = self.next_handle();
+        self.handles.insert(fh, Handle::Method {
+          input: Vec::new(),
+          written: false,
+          output: None,
+          position: 0,
+        });
+        reply.opened(fh, FOPEN_DIRECT_IO)
+      }
+
+      // Only a reader gets a new token, writing one back must not replace it.
+      NodeKind::Confirm => {
+        let key = match method_key(&node) {
+          Some((key, _, _)) => key,
+          None => return reply.error(ENOENT),
+        };
+        let token = if flags & O_ACCMODE != O_WRONLY {
+          let mut token = match self.guard.token(&key) {
+            Ok(token) => token.into_bytes(),
+            Err(_) => return reply.error(EIO),
+          };
+          token.push(b'\n');
+          token
+        } else {
+          Vec::new()
+        };
+
+        let fh = self.next_handle();
+        self.handles.insert(fh, Handle::Confirm { key, token, input: Vec::new() });
+        reply.opened(fh, FOPEN_DIRECT_IO)
+      }
+
+      NodeKind::Signal => {
+        match self.watch_signal(&node) {
+          Ok(watch) => {
+            let fh = self.next_handle();
+            self.handles.insert(fh, Handle::Signal {
+              bus: node.bus,
+              watch,
+              queue: VecDeque::new(),
+              waiting: VecDeque::new(),
+              poll: None,
+            });
+            reply.opened(fh, FOPEN_DIRECT_IO)
+          }
+          Err(errno) => reply.error(self.keep_error(ino, errno)),
+        }
+      }
+
+      _ => reply.opened(0, 0),
+    }
+  }
+
+  fn write(&mut self, _req: &Request, _ino: u64, fh: u64, offset: i64, data: &[u8], _write_flags: u32, _flags: i32,
+           _lock_owner: Option<u64>, reply: ReplyWrite) {
+    match self.handles.get_mut(&fh) {
+      Some(&mut Handle::Property { ref mut buffer, ref mut dirty, .. }) => {
+        let end = match (offset as u64).checked_add(data.len() as u64) {
+          Some(end) if end <= MAX_INPUT_SIZE => end as usize,
+          _ => return reply.error(EFBIG),
+        };
+        let offset = offset as usize;
+        if buffer.len() < end {
+          buffer.resize(end, 0);
+        }
+        buffer[offset..end].copy_from_slice(data);
+        *dirty = true;
+        reply.written(data.len() as u32)
+      }
+      Some(&mut Handle::Method { ref mut input, ref mut written, ref mut output, ref mut position, .. }) => {
+        // Writing after the reply has been read starts a new call.
+        if output.is_some() {
+          input.clear();
+          *output = None;
+          *position = 0;
+        }
+        if (input.len() + data.len()) as u64 > MAX_INPUT_SIZE {
+          return reply.error(EFBIG);
+        }
+        input.extend_from_slice(data);
+        *written = true;
+        reply.written(data.len() as u32)
+      }
+      Some(&mut Handle::Confirm { ref mut input, .. }) => {
+        if (input.len() + data.len()) as u64 > MAX_INPUT_SIZE {
+          return reply.error(EFBIG);
+        }
+        input.extend_from_slice(data);
+        reply.written(data.len() as u32)
+      }
+      _ => reply.error(EACCES),
+    }
+  }
+
+  fn flush(&mut self, _req: &Request, ino: u64, fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+    match self.flush_handle(ino, fh) {
+      Ok(()) => reply.ok(),
+      Err(errno) => reply.error(self.keep_error(ino, errno)),
+    }
+  }
+
+  fn release(&mut self, _req: &Request, ino: u64, fh: u64, _flags: i32, _lock_owner: Option<u64>, _flush: bool, reply: ReplyEmpty) {
+    // Nobody is left to see an error at this point, flush has already reported it.
+    if let Err(errno) = self.flush_handle(ino, fh) {
+      self.keep_error(ino, errno);
+    }
+    if let Some(Handle::Signal { bus, watch, .. }) = self.handles.remove(&fh) {
+      self.remove_match(bus, &watch.rule);
+    }
+    reply.ok();
+  }
+
+  /// Signal files and watched property files are readable once there is something
+  /// new to read, pollers waiting for that are woken by the bus thread.
+  fn poll(&mut self, _req: &Request, _ino: u64, fh: u64, ph: PollHandle, _events: u32, flags: u32, reply: ReplyPoll) {
+    self.catch_up();
+    let (ready, poll) = match self.handles.get_mut(&fh) {
+      Some(&mut Handle::Signal { ref queue, ref mut poll, .. }) => (!queue.is_empty(), poll),
+      Some(&mut Handle::Property { watching: true, changed, ref mut poll, .. }) => (changed, poll),
+      _ => return reply.poll(DEFAULT_POLLMASK),
+    };
+
+    if ready {
+      reply.poll((POLLIN | POLLRDNORM) as u32)
+    } else {
+      if flags & FUSE_POLL_SCHEDULE_NOTIFY != 0 {
+        *poll = Some(ph);
+      }
+      reply.poll(0)
+    }
+  }
+}
+
+/// The file system as the FUSE session and the bus thread share it.
+#[derive(Clone)]
+struct Mount(Arc<Mutex<DbusFs>>);
+
+impl Mount {
+  fn new(fs: DbusFs) -> Mount {
+    Mount(Arc::new(Mutex::new(fs)))
+  }
+
+  /// A request that panicked leaves nothing half done the next one can't cope with.
+  fn lock(&self) -> MutexGuard<'_, DbusFs> {
+    self.0.lock().unwrap_or_else(|err| err.into_inner())
+  }
+
+  /// Handle signals as they come in a thread of their own, rather than on the next
+  /// request, and tell the kernel what they changed.
+  fn watch_buses(&self, notifier: Notifier) -> io::Result<()> {
+    let mut fds = self.lock()
+                      .buses
+                      .iter_mut()
+                      .map(|&mut (_, ref mut conn)| {
+                        conn.set_watch_enabled(true);
+                        libc::pollfd {
+                          fd: conn.watch().fd,
+                          events: libc::POLLIN,
+                          revents: 0,
+                        }
+                      })
+                      .collect::<Vec<_>>();
+
+    let fs = self.clone();
+    thread::Builder::new().name("bus".to_owned()).spawn(move || {
+      let mut touching = true;
+      loop {
+        unsafe {
+          libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, BUS_POLL_MS);
+        }
+        // A closed connection stays readable, it's only waited on for the timeout from then on.
+        for fd in &mut fds {
+          if fd.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0 {
+            fd.fd = -1;
+          }
+        }
+
+        let notices = {
+          let mut fs = fs.lock();
+          fs.catch_up();
+          fs.interrupt_reads();
+          mem::take(&mut fs.notices)
+        };
+        notify(&notifier, notices, &mut touching);
+      }
+    }).map(|_| ())
+  }
+}
+
+/// Tell the kernel about changes. Nothing may hold the file system meanwhile: the
+/// kernel waits for requests in the directories involved, and touching makes some.
+///
+/// A mount with `-o ro` can't be touched, the kernel refuses with EROFS before
+/// asking us. Touching stops then: reads and poll still see the changes, inotify
+/// watchers get no events.
+fn notify(notifier: &Notifier, notices: Vec<Notice>, touching: &mut bool) {
+  for notice in notices {
+    match notice {
+      Notice::Entry(parent, name) => {
+        let _ = notifier.inval_entry(parent, OsStr::new(&name));
+      }
+      Notice::Inode(ino, path) => {
+        let _ = notifier.inval_inode(ino, 0, 0);
+        if !*touching {
+          continue;
+        }
+        match touch(&path) {
+          Ok(()) => (),
+          // Gone meanwhile, nobody can be watching it.
+          Err(ref err) if err.raw_os_error() == Some(ENOENT) => (),
+          Err(ref err) if err.raw_os_error() == Some(EROFS) => {
+            let _ = writeln!(io::stderr(), "dbusfs: read-only mount, inotify watchers won't hear of changes");
+            *touching = false;
+          }
+          Err(err) => {
+            let _ = writeln!(io::stderr(), "dbusfs: can't touch {} for inotify watchers: {}", path.display(), err);
+          }
+        }
+      }
+    }
+  }
+}
+
+/// Set the modification time of a file through the mount. The kernel has no event
+/// for inotify watchers when told an inode changed, this gives them `IN_MODIFY`.
+fn touch(path: &Path) -> io::Result<()> {
+  let path = CString::new(path.as_os_str().as_bytes()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "bad path"))?;
+  let times = [libc::timespec {
+                 tv_sec: 0,
+                 tv_nsec: libc::UTIME_OMIT,
+               },
+               libc::timespec {
+                 tv_sec: 0,
+                 tv_nsec: libc::UTIME_NOW,
+               }];
+  if unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), 0) } == -1 {
+    return Err(io::Error::last_os_error());
+  }
+  Ok(())
+}
+
+/// Every request handles the signals it got along with its calls, or they would wait
+/// in the connection for the bus thread, which only sees what comes on the socket.
+impl Filesystem for Mount {
+  fn init(&mut self, req: &Request, config: &mut KernelConfig) -> Result<(), c_int> {
+    self.lock().init(req, config)
+  }
+
+  fn lookup(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+    let mut fs = self.lock();
+    fs.lookup(req, parent, name, reply);
+    fs.catch_up();
+  }
+
+  fn forget(&mut self, req: &Request, ino: u64, nlookup: u64) {
+    self.lock().forget(req, ino, nlookup);
+  }
+
+  fn getattr(&mut self, req: &Request, ino: u64, fh: Option<u64>, reply: ReplyAttr) {
+    let mut fs = self.lock();
+    fs.getattr(req, ino, fh, reply);
+    fs.catch_up();
+  }
+
+  fn setattr(&mut self, req: &Request, ino: u64, mode: Option<u32>, uid: Option<u32>, gid: Option<u32>, size: Option<u64>,
+             atime: Option<TimeOrNow>, mtime: Option<TimeOrNow>, ctime: Option<SystemTime>, fh: Option<u64>,
+             crtime: Option<SystemTime>, chgtime: Option<SystemTime>, bkuptime: Option<SystemTime>, flags: Option<u32>,
+             reply: ReplyAttr) {
+    let mut fs = self.lock();
+    fs.setattr(req, ino, mode, uid, gid, size, atime, mtime, ctime, fh, crtime, chgtime, bkuptime, flags, reply);
+    fs.catch_up();
+  }
+
+  fn open(&mut self, req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+    let mut fs = self.lock();
+    fs.open(req, ino, flags, reply);
+    fs.catch_up();
+  }
+
+  fn read(&mut self, req: &Request, ino: u64, fh: u64, offset: i64, size: u32, flags: i32, lock_owner: Option<u64>, reply: ReplyData) {
+    let mut fs = self.lock();
+    fs.read(req, ino, fh, offset, size, flags, lock_owner, reply);
+    fs.catch_up();
+  }
+
+  fn write(&mut self, req: &Request, ino: u64, fh: u64, offset: i64, data: &[u8], write_flags: u32, flags: i32,
+           lock_owner: Option<u64>, reply: ReplyWrite) {
+    self.lock().write(req, ino, fh, offset, data, write_flags, flags, lock_owner, reply);
+  }
+
+  fn flush(&mut self, req: &Request, ino: u64, fh: u64, lock_owner: u64, reply: ReplyEmpty) {
+    let mut fs = self.lock();
+    fs.flush(req, ino, fh, lock_owner, reply);
+    fs.catch_up();
+  }
+
+  fn release(&mut self, req: &Request, ino: u64, fh: u64, flags: i32, lock_owner: Option<u64>, flush: bool, reply: ReplyEmpty) {
+    let mut fs = self.lock();
+    fs.release(req, ino, fh, flags, lock_owner, flush, reply);
+    fs.catch_up();
+  }
+
+  fn readdir(&mut self, req: &Request, ino: u64, fh: u64, offset: i64, reply: ReplyDirectory) {
+    let mut fs = self.lock();
+    fs.readdir(req, ino, fh, offset, reply);
+    fs.catch_up();
+  }
+
+  fn setxattr(&mut self, req: &Request, ino: u64, name: &OsStr, value: &[u8], flags: i32, position: u32, reply: ReplyEmpty) {
+    self.lock().setxattr(req, ino, name, value, flags, position, reply);
+  }
+
+  fn getxattr(&mut self, req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+    let mut fs = self.lock();
+    fs.getxattr(req, ino, name, size, reply);
+    fs.catch_up();
+  }
+
+  fn listxattr(&mut self, req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+    let mut fs = self.lock();
+    fs.listxattr(req, ino, size, reply);
+    fs.catch_up();
+  }
+
+  fn removexattr(&mut self, req: &Request, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+    self.lock().removexattr(req, ino, name, reply);
+  }
+
+  fn poll(&mut self, req: &Request, ino: u64, fh: u64, ph: PollHandle, events: u32, flags: u32, reply: ReplyPoll) {
+    self.lock().poll(req, ino, fh, ph, events, flags, reply);
+  }
+}
+
+extern "C" {
+  // Missing from libc 0.2.
+  fn initgroups(user: *const libc::c_char, group: libc::gid_t) -> c_int;
+}
+
+/// Switch to another user before connecting, so that the bus sees their credentials
+/// and their session bus is found rather than ours.
+fn become_user(uid: u32) -> io::Result<()> {
+  if uid == users::get_current_uid() {
+    return Ok(());
+  }
+
+  let user = match get_user_by_uid(uid) {
+    Some(user) => user,
+    None => return Err(io::Error::new(io::ErrorKind::NotFound, "no such user")),
+  };
+
+  unsafe {
+    let name = match CString::new(user.name.clone()) {
+      Ok(name) => name,
+      Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid user name")),
+    };
+    if libc::setgid(user.primary_group) == -1 || initgroups(name.as_ptr(), user.primary_group) == -1 ||
+       libc::setuid(uid) == -1 {
+      return Err(io::Error::last_os_error());
+    }
+  }
+
+  env::remove_var("DBUS_SESSION_BUS_ADDRESS");
+  env::set_var("XDG_RUNTIME_DIR", format!("/run/user/{}", uid));
+  env::set_var("USER", &user.name);
+  Ok(())
+}
+
+fn main() {
+  let args = env::args().collect::<Vec<_>>();
+  let program = args.first().map_or("dbusfs", |arg| &**arg).to_owned();
+
+  // Linked to as `mount.dbusfs` too, to be run by mount(8) for `dbusfs` entries in fstab:
+  // `ln -s dbusfs /sbin/mount.dbusfs`, a crate can't build a binary with a dot in its name.
+  let helper = Path::new(&program).file_name().is_some_and(|name| name == "mount.dbusfs");
+  let (parsed, usage) = if helper {
+    (cli::parse_helper(&args), cli::helper_usage(&program))
+  } else {
+    (cli::parse(&args), cli::usage(&program))
+  };
+
+  let config = match parsed {
+    Ok(Some(config)) => config,
+    Ok(None) => {
+      print!("{}", usage);
+      return;
+    }
+    Err(err) => {
+      let _ = writeln!(io::stderr(), "{}: {}\n\n{}", program, err, usage);
+      process::exit(daemon::EX_USAGE);
+    }
+  };
+
+  if config.fake {
+    return;
+  }
+
+  // A daemon runs from the root directory, a relative mountpoint has to be resolved first.
+  let mountpoint = env::current_dir().map(|dir| dir.join(&config.mountpoint)).unwrap_or_else(|_| config.mountpoint.clone().into());
+
+  if let Some(uid) = config.uid {
+    if let Err(err) = become_user(uid) {
+      let _ = writeln!(io::stderr(), "{}: can't switch to user {}: {}", program, uid, err);
+      process::exit(daemon::EX_NOPERM);
+    }
+  }
+
+  // Until the mount is live the parent keeps waiting, and errors still reach its terminal.
+  let daemon = if config.foreground {
+    Daemon::default()
+  } else {
+    match daemon::daemonize() {
+      Ok(daemon) => daemon,
+      Err(err) => {
+        let _ = writeln!(io::stderr(), "{}: can't run as a daemon: {}", program, err);
+        process::exit(daemon::EX_OSERR);
+      }
+    }
+  };
+
+  let mut fs = match DbusFs::new(&config.buses) {
+    Ok(fs) => fs,
+    Err(err) => {
+      let _ = writeln!(io::stderr(), "{}: can't connect to the bus: {}", program, err.message().unwrap_or("unknown error"));
+      process::exit(match err.name() {
+        Some(name) if name == DBUS_ACCESS_ERROR => daemon::EX_NOPERM,
+        _ => daemon::EX_UNAVAILABLE,
+      });
+    }
+  };
+  let options = config.fuse_mount_options();
+  fs.format = config.format;
+  fs.read_only = config.read_only;
+  fs.filter = config.filter;
+  fs.guard = config.guard;
+  fs.timeouts = config.timeouts;
+  fs.errnos = config.errnos;
+  fs.cache = Cache::new(config.cache_ttl);
+  fs.ttl = Duration::from_secs(config.ttl as u64);
+  fs.mountpoint = mountpoint.clone();
+  fs.daemon = daemon;
+
+  daemon::unmount_on_signals(mountpoint.clone());
+
+  let fs = Mount::new(fs);
+  let mut session = match Session::new(fs.clone(), &mountpoint, &options) {
+    Ok(session) => session,
+    Err(err) => {
+      let _ = writeln!(io::stderr(), "{}: can't mount {}: {}", program, mountpoint.display(), err);
+      process::exit(daemon::EX_OSERR);
+    }
+  };
+  if let Err(err) = fs.watch_buses(session.notifier()) {
+    let _ = writeln!(io::stderr(), "{}: can't watch the bus: {}", program, err);
+    process::exit(daemon::EX_OSERR);
+  }
+
+  if let Err(err) = session.run() {
+    let _ = writeln!(io::stderr(), "{}: {}", program, err);
+    process::exit(daemon::EX_SOFTWARE);
+  }
 }
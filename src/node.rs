@@ -3,7 +3,7 @@
 // `for` loops take ownership on iterator, but I need to pass XML events iterator
 // around and do some fancy things with it, so I use `while let` instead
 // to release iterator ownership when it is not being advanced.
-#![allow(while_let_on_iterator)]
+#![allow(clippy::while_let_on_iterator)]
 
 use std::io::Read;
 use std::collections::BTreeMap;
@@ -12,10 +12,21 @@ use xml::name::OwnedName;
 use xml::attribute::OwnedAttribute;
 use xml::reader::Events;
 
-mod types {
+use self::types::Full;
+
+pub mod types {
+  use std::fmt;
   use std::str::FromStr;
 
-  enum Basic {
+  /// Longest signature allowed by the D-Bus specification.
+  pub const MAX_SIGNATURE_LEN: usize = 255;
+  /// Deepest nesting of arrays, and separately of structs and dict entries.
+  pub const MAX_NESTING: usize = 32;
+  /// Deepest nesting of arrays, structs and dict entries together.
+  pub const MAX_TOTAL_NESTING: usize = 64;
+
+  #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+  pub enum Basic {
     Byte, // y
     Bool, // b
     Int16, // n
@@ -29,58 +40,284 @@ mod types {
     String, // s
     ObjPath, // o
     TypeSig, // g
-    Variant, // v
   }
 
-  enum Full {
+  #[derive(Debug, PartialEq, Eq, Clone)]
+  pub enum Full {
     Basic(Basic),
+    Variant, // v
     Struct(Vec<Full>), // r, (...)
     Array(Box<Full>), // a...
     Dict(Basic, Box<Full>), // e, {...}
   }
 
-  enum TypeSigError {
-    InvalidChar,
-    EndOfStruct,
-    EndOfDictEntry,
+  /// Signature errors, each with the byte position it was found at.
+  #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+  pub enum TypeSigError {
+    InvalidChar(usize),
+    EndOfSignature(usize),
+    EndOfStruct(usize),
+    EndOfDictEntry(usize),
+    EmptyStruct(usize),
+    DictKeyNotBasic(usize),
+    DictNotInArray(usize),
+    TooDeep(usize),
+    TooLong(usize),
+    TrailingChars(usize),
   }
 
-  impl FromStr for Basic {
-    type Err = ();
-    fn from_str(s: &str) -> Result<Basic, ()> {
+  impl Basic {
+    pub fn from_char(c: char) -> Option<Basic> {
       use self::Basic::*;
-      Ok(match s[0..1] {
-        "y" => Byte,
-        "b" => Bool,
-        "n" => Int16,
-        "q" => UInt16,
-        "i" => Int32,
-        "u" => UInt32,
-        "x" => Int64,
-        "t" => UInt64,
-        "d" => Double,
-        "h" => UnixFd,
-        "s" => String,
-        "o" => ObjPath,
-        "g" => TypeSig,
-        "v" => Variant,
-        _ => return Err(TypeSigError::InvalidChar)
+      Some(match c {
+        'y' => Byte,
+        'b' => Bool,
+        'n' => Int16,
+        'q' => UInt16,
+        'i' => Int32,
+        'u' => UInt32,
+        'x' => Int64,
+        't' => UInt64,
+        'd' => Double,
+        'h' => UnixFd,
+        's' => String,
+        'o' => ObjPath,
+        'g' => TypeSig,
+        _ => return None,
       })
     }
+
+    pub fn to_char(self) -> char {
+      use self::Basic::*;
+      match self {
+        Byte => 'y',
+        Bool => 'b',
+        Int16 => 'n',
+        UInt16 => 'q',
+        Int32 => 'i',
+        UInt32 => 'u',
+        Int64 => 'x',
+        UInt64 => 't',
+        Double => 'd',
+        UnixFd => 'h',
+        String => 's',
+        ObjPath => 'o',
+        TypeSig => 'g',
+      }
+    }
+  }
+
+  impl TypeSigError {
+    pub fn position(&self) -> usize {
+      use self::TypeSigError::*;
+      match *self {
+        InvalidChar(pos) | EndOfSignature(pos) | EndOfStruct(pos) | EndOfDictEntry(pos) | EmptyStruct(pos) |
+        DictKeyNotBasic(pos) | DictNotInArray(pos) | TooDeep(pos) | TooLong(pos) | TrailingChars(pos) => pos,
+      }
+    }
+  }
+
+  impl fmt::Display for TypeSigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      use self::TypeSigError::*;
+      let msg = match *self {
+        InvalidChar(_) => "invalid type code",
+        EndOfSignature(_) => "unexpected end of signature",
+        EndOfStruct(_) => "unterminated struct",
+        EndOfDictEntry(_) => "dict entry must have exactly a key and a value",
+        EmptyStruct(_) => "empty struct",
+        DictKeyNotBasic(_) => "dict entry key must be a basic type",
+        DictNotInArray(_) => "dict entry outside of an array",
+        TooDeep(_) => "containers nested too deep",
+        TooLong(_) => "signature too long",
+        TrailingChars(_) => "more than one complete type",
+      };
+      write!(f, "{} at position {}", msg, self.position())
+    }
+  }
+
+  impl fmt::Display for Basic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}", self.to_char())
+    }
+  }
+
+  impl fmt::Display for Full {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match *self {
+        Full::Basic(ref b) => write!(f, "{}", b),
+        Full::Variant => write!(f, "v"),
+        Full::Struct(ref fields) => {
+          write!(f, "(")?;
+          for field in fields {
+            write!(f, "{}", field)?;
+          }
+          write!(f, ")")
+        }
+        Full::Array(ref elem) => write!(f, "a{}", elem),
+        Full::Dict(ref key, ref value) => write!(f, "{{{}{}}}", key, value),
+      }
+    }
+  }
+
+  impl FromStr for Basic {
+    type Err = TypeSigError;
+    fn from_str(s: &str) -> Result<Basic, TypeSigError> {
+      let mut chars = s.chars();
+      match (chars.next().and_then(Basic::from_char), chars.next()) {
+        (Some(basic), None) => Ok(basic),
+        (Some(_), Some(_)) => Err(TypeSigError::TrailingChars(1)),
+        (None, _) => Err(TypeSigError::InvalidChar(0)),
+      }
+    }
   }
 
   impl FromStr for Full {
-    type Err = ();
-    fn from_str(s: &str) -> Result<Full, ()> {
-      use self::Basic::*;
-      s.parse::<Basic>().map(Basic).or_else(|_| match s[0..1] {
-        "(" => 
+    type Err = TypeSigError;
+    fn from_str(s: &str) -> Result<Full, TypeSigError> {
+      let mut parser = Parser::new(s)?;
+      let full = parser.complete_type()?;
+      match parser.pos {
+        pos if pos < s.len() => Err(TypeSigError::TrailingChars(pos)),
+        _ => Ok(full),
+      }
+    }
+  }
+
+  /// Parse a signature made of any number of complete types, like a method's input.
+  pub fn parse_signature(s: &str) -> Result<Vec<Full>, TypeSigError> {
+    let mut parser = Parser::new(s)?;
+    let mut types = Vec::new();
+    while parser.pos < s.len() {
+      types.push(parser.complete_type()?);
+    }
+    Ok(types)
+  }
+
+  struct Parser<'a> {
+    sig: &'a [u8],
+    pos: usize,
+    arrays: usize,
+    structs: usize,
+  }
+
+  impl<'a> Parser<'a> {
+    fn new(s: &'a str) -> Result<Parser<'a>, TypeSigError> {
+      if s.len() > MAX_SIGNATURE_LEN {
+        return Err(TypeSigError::TooLong(MAX_SIGNATURE_LEN));
+      }
+
+      Ok(Parser {
+        sig: s.as_bytes(),
+        pos: 0,
+        arrays: 0,
+        structs: 0,
       })
     }
+
+    fn peek(&self) -> Option<u8> {
+      self.sig.get(self.pos).cloned()
+    }
+
+    fn check_depth(&self, pos: usize) -> Result<(), TypeSigError> {
+      if self.arrays > MAX_NESTING || self.structs > MAX_NESTING || self.arrays + self.structs > MAX_TOTAL_NESTING {
+        Err(TypeSigError::TooDeep(pos))
+      } else {
+        Ok(())
+      }
+    }
+
+    fn complete_type(&mut self) -> Result<Full, TypeSigError> {
+      let pos = self.pos;
+      let c = match self.peek() {
+        Some(c) => c,
+        None => return Err(TypeSigError::EndOfSignature(pos)),
+      };
+      self.pos += 1;
+
+      match c {
+        b'a' => {
+          self.arrays += 1;
+          self.check_depth(pos)?;
+          let elem = match self.peek() {
+            Some(b'{') => self.dict_entry()?,
+            _ => self.complete_type()?,
+          };
+          self.arrays -= 1;
+          Ok(Full::Array(Box::new(elem)))
+        }
+
+        b'(' => {
+          self.structs += 1;
+          self.check_depth(pos)?;
+          let mut fields = Vec::new();
+          loop {
+            match self.peek() {
+              Some(b')') => break,
+              Some(_) => fields.push(self.complete_type()?),
+              None => return Err(TypeSigError::EndOfStruct(pos)),
+            }
+          }
+          if fields.is_empty() {
+            return Err(TypeSigError::EmptyStruct(pos));
+          }
+          self.pos += 1;
+          self.structs -= 1;
+          Ok(Full::Struct(fields))
+        }
+
+        b'{' => Err(TypeSigError::DictNotInArray(pos)),
+        b'v' => Ok(Full::Variant),
+        c => Basic::from_char(c as char).map(Full::Basic).ok_or(TypeSigError::InvalidChar(pos)),
+      }
+    }
+
+    /// Parse a `{...}` dict entry, the current character is its opening brace.
+    fn dict_entry(&mut self) -> Result<Full, TypeSigError> {
+      let pos = self.pos;
+      self.pos += 1;
+      self.structs += 1;
+      self.check_depth(pos)?;
+
+      let key = match self.peek().and_then(|c| Basic::from_char(c as char)) {
+        Some(key) => key,
+        None if self.peek().is_none() => return Err(TypeSigError::EndOfDictEntry(pos)),
+        None => return Err(TypeSigError::DictKeyNotBasic(self.pos)),
+      };
+      self.pos += 1;
+
+      let value = match self.peek() {
+        Some(b'}') | None => return Err(TypeSigError::EndOfDictEntry(pos)),
+        Some(_) => self.complete_type()?,
+      };
+
+      match self.peek() {
+        Some(b'}') => self.pos += 1,
+        _ => return Err(TypeSigError::EndOfDictEntry(pos)),
+      }
+      self.structs -= 1;
+      Ok(Full::Dict(key, Box::new(value)))
+    }
   }
 }
 
 
+/// Annotation telling whether `PropertiesChanged` is emitted, on a property or its interface.
+pub static EMITS_CHANGED_SIGNAL: &str = "org.freedesktop.DBus.Property.EmitsChangedSignal";
+
+/// Values of the `EmitsChangedSignal` annotation.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EmitsChanged {
+  /// The new value comes with the signal, the default.
+  True,
+  /// The signal only tells the value is no longer valid.
+  Invalidates,
+  /// The value never changes.
+  Const,
+  /// Changes are not told about.
+  False,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct NodeInfo {
   pub nodes: Vec<Node>,
@@ -104,9 +341,8 @@ pub struct Interface {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Property {
   pub name: String,
-  pub typesig: String,
+  pub typesig: Full,
   pub access: Access,
-  // TODO: not supported
   pub annotations: BTreeMap<String, String>,
 }
 
@@ -127,7 +363,7 @@ pub struct Signal {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Argument {
   pub name: String,
-  pub typesig: String,
+  pub typesig: Full,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -164,16 +400,10 @@ impl NodeInfo {
       if let Ok(StartElement { name: OwnedName { local_name, .. }, attributes: attrs, .. }) = ev {
         match &*local_name {
           "node" => {
-            match get_name(attrs) {
-              Some(name) => nodeinfo.nodes.push(Node { name: name }),
-              None => (),
-            }
+            if let Some(name) = get_name(attrs) { nodeinfo.nodes.push(Node { name }) }
           }
           "interface" => {
-            match get_name(attrs) {
-              Some(name) => nodeinfo.interfaces.push(Interface::from_xml(name, events)),
-              None => (),
-            }
+            if let Some(name) = get_name(attrs) { nodeinfo.interfaces.push(Interface::from_xml(name, events)) }
           }
           _ => (),
         }
@@ -189,7 +419,7 @@ impl Interface {
     use xml::reader::XmlEvent::*;
 
     let mut iface = Interface {
-      name: name,
+      name,
       methods: Vec::new(),
       properties: Vec::new(),
       signals: Vec::new(),
@@ -201,17 +431,17 @@ impl Interface {
         Ok(StartElement { name: OwnedName { local_name, .. }, attributes: attrs, .. }) => {
           match &*local_name {
             "method" => {
-              if let Some(name) = get_name(attrs) {
-                iface.methods.push(Method::from_xml(name, events));
+              if let Some(method) = get_name(attrs).and_then(|name| Method::from_xml(name, events)) {
+                iface.methods.push(method);
               }
             }
-            "signals" => {
-              if let Some(name) = get_name(attrs) {
-                iface.signals.push(Signal::from_xml(name, events));
+            "signal" => {
+              if let Some(signal) = get_name(attrs).and_then(|name| Signal::from_xml(name, events)) {
+                iface.signals.push(signal);
               }
             }
             "property" => {
-              if let Some(prop) = Property::from_xml(attrs) {
+              if let Some(prop) = Property::from_xml(attrs, events) {
                 iface.properties.push(prop);
               }
             }
@@ -232,23 +462,39 @@ impl Interface {
   }
 }
 
+impl Interface {
+  /// Whether a property of this interface tells about its changes, the annotation on
+  /// the property goes before the one on the interface. Unknown values count as `true`.
+  pub fn emits_changed(&self, prop: &Property) -> EmitsChanged {
+    match prop.annotations.get(EMITS_CHANGED_SIGNAL).or_else(|| self.annotations.get(EMITS_CHANGED_SIGNAL)).map(|v| &**v) {
+      Some("invalidates") => EmitsChanged::Invalidates,
+      Some("const") => EmitsChanged::Const,
+      Some("false") => EmitsChanged::False,
+      _ => EmitsChanged::True,
+    }
+  }
+}
+
 impl Signal {
-  fn from_xml<R: Read>(name: String, events: &mut Events<R>) -> Signal {
+  /// A signal with an argument of an unusable type is left out, it couldn't be decoded.
+  fn from_xml<R: Read>(name: String, events: &mut Events<R>) -> Option<Signal> {
     use xml::reader::XmlEvent::*;
 
     let mut signal = Signal {
-      name: name,
+      name,
       args: Vec::new(),
       annotations: BTreeMap::new(),
     };
+    let mut usable = true;
 
     while let Some(ev) = events.next() {
       match ev {
         Ok(StartElement { name: OwnedName { local_name, .. }, attributes: attrs, .. }) => {
           match &*local_name {
             "arg" => {
-              if let Some(arg) = Argument::from_xml(attrs) {
-                signal.args.push(arg.0);
+              match Argument::from_xml(attrs) {
+                Some(arg) => signal.args.push(arg.0),
+                None => usable = false,
               }
             }
             "annotation" => {
@@ -264,26 +510,30 @@ impl Signal {
       }
     }
 
-    signal
+    if usable { Some(signal) } else { None }
   }
 }
 impl Method {
-  fn from_xml<R: Read>(name: String, events: &mut Events<R>) -> Method {
+  /// A method with an argument of an unusable type is left out, it couldn't be called
+  /// with the right arguments.
+  fn from_xml<R: Read>(name: String, events: &mut Events<R>) -> Option<Method> {
     use xml::reader::XmlEvent::*;
 
     let mut method = Method {
-      name: name,
+      name,
       args: Vec::new(),
       annotations: BTreeMap::new(),
     };
+    let mut usable = true;
 
     while let Some(ev) = events.next() {
       match ev {
         Ok(StartElement { name: OwnedName { local_name, .. }, attributes: attrs, .. }) => {
           match &*local_name {
             "arg" => {
-              if let Some(arg) = Argument::from_xml(attrs) {
-                method.args.push(arg);
+              match Argument::from_xml(attrs) {
+                Some(arg) => method.args.push(arg),
+                None => usable = false,
               }
             }
             "annotation" => {
@@ -299,7 +549,7 @@ impl Method {
       }
     }
 
-    method
+    if usable { Some(method) } else { None }
   }
 }
 
@@ -313,7 +563,7 @@ impl Argument {
     for attr in attrs {
       match &*attr.name.local_name {
         "name" => name = Some(attr.value),
-        "type" => typesig = Some(attr.value),
+        "type" => typesig = attr.value.parse().ok(),
         "direction" => {
           match &*attr.value {
             "in" => dir = Direction::In,
@@ -325,20 +575,22 @@ impl Argument {
       }
     }
 
-    if let (Some(name), Some(typesig)) = (name, typesig) {
-      Some((Argument {
-        name: name,
-        typesig: typesig,
+    // Argument names are optional, but a missing or invalid type makes the argument,
+    // and whatever it belongs to, unusable.
+    typesig.map(|typesig| {
+      (Argument {
+        name: name.unwrap_or_else(String::new),
+        typesig,
       },
-            dir))
-    } else {
-      None
-    }
+       dir)
+    })
   }
 }
 
 impl Property {
-  fn from_xml(attrs: Vec<OwnedAttribute>) -> Option<Property> {
+  fn from_xml<R: Read>(attrs: Vec<OwnedAttribute>, events: &mut Events<R>) -> Option<Property> {
+    use xml::reader::XmlEvent::*;
+
     let attrs = attrs.into_iter();
     let mut name = None;
     let mut typesig = None;
@@ -347,7 +599,7 @@ impl Property {
     for attr in attrs {
       match &*attr.name.local_name {
         "name" => name = Some(attr.value),
-        "type" => typesig = Some(attr.value),
+        "type" => typesig = attr.value.parse().ok(),
         "access" | "direction" => {
           access = match &*attr.value {
             "read" => Access::Read,
@@ -359,12 +611,26 @@ impl Property {
       }
     }
 
+    // Annotations are read even for a property that is left out, to get past its end.
+    let mut annotations = BTreeMap::new();
+    while let Some(ev) = events.next() {
+      match ev {
+        Ok(StartElement { name: OwnedName { ref local_name, .. }, attributes: attrs, .. }) if local_name == "annotation" => {
+          if let Some((name, value)) = get_name_value(attrs) {
+            annotations.insert(name, value);
+          }
+        }
+        Ok(EndElement { name: OwnedName { ref local_name, .. }, .. }) if local_name == "property" => break,
+        _ => (),
+      }
+    }
+
     if let (Some(name), Some(typesig)) = (name, typesig) {
       Some(Property {
-        name: name,
-        typesig: typesig,
-        access: access,
-        annotations: BTreeMap::new(),
+        name,
+        typesig,
+        access,
+        annotations,
       })
     } else {
       None
@@ -390,3 +656,82 @@ fn get_name_value<I: IntoIterator<Item = OwnedAttribute>>(attrs: I) -> Option<(S
 
   if let (Some(name), Some(value)) = (name, value) { Some((name, value)) } else { None }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::types::{self, Basic, Full, TypeSigError};
+
+  fn sig(s: &str) -> Result<Full, TypeSigError> {
+    s.parse()
+  }
+
+  #[test]
+  fn signatures() {
+    assert_eq!(sig("a{sv}"), Ok(Full::Array(Box::new(Full::Dict(Basic::String, Box::new(Full::Variant))))));
+    assert_eq!(sig("(is)"), Ok(Full::Struct(vec![Full::Basic(Basic::Int32), Full::Basic(Basic::String)])));
+    for s in &["a{sa(iv)}", "aay", "a(oa{sv})", "v", "h"] {
+      assert_eq!(sig(s).unwrap().to_string(), *s);
+    }
+    assert_eq!(types::parse_signature("sa{sv}i").map(|types| types.len()), Ok(3));
+    assert_eq!(types::parse_signature(""), Ok(vec![]));
+  }
+
+  #[test]
+  fn bad_signatures() {
+    assert_eq!(sig(""), Err(TypeSigError::EndOfSignature(0)));
+    assert_eq!(sig("z"), Err(TypeSigError::InvalidChar(0)));
+    assert_eq!(sig("(iz)"), Err(TypeSigError::InvalidChar(2)));
+    assert_eq!(sig("ii"), Err(TypeSigError::TrailingChars(1)));
+    assert_eq!(sig("a(ix"), Err(TypeSigError::EndOfStruct(1)));
+    assert_eq!(sig("()"), Err(TypeSigError::EmptyStruct(0)));
+    assert_eq!(sig("a"), Err(TypeSigError::EndOfSignature(1)));
+    assert_eq!(types::parse_signature("sa"), Err(TypeSigError::EndOfSignature(2)));
+  }
+
+  #[test]
+  fn bad_dicts() {
+    assert_eq!(sig("{sv}"), Err(TypeSigError::DictNotInArray(0)));
+    assert_eq!(sig("({sv})"), Err(TypeSigError::DictNotInArray(1)));
+    assert_eq!(sig("a{vs}"), Err(TypeSigError::DictKeyNotBasic(2)));
+    assert_eq!(sig("a{(i)s}"), Err(TypeSigError::DictKeyNotBasic(2)));
+    assert_eq!(sig("a{s"), Err(TypeSigError::EndOfDictEntry(1)));
+    assert_eq!(sig("a{s}"), Err(TypeSigError::EndOfDictEntry(1)));
+    assert_eq!(sig("a{sii}"), Err(TypeSigError::EndOfDictEntry(1)));
+  }
+
+  #[test]
+  fn limits() {
+    let arrays = |n| format!("{}y", "a".repeat(n));
+    let structs = |n| format!("{}y{}", "(".repeat(n), ")".repeat(n));
+    assert!(sig(&arrays(types::MAX_NESTING)).is_ok());
+    assert_eq!(sig(&arrays(types::MAX_NESTING + 1)), Err(TypeSigError::TooDeep(types::MAX_NESTING)));
+    assert!(sig(&structs(types::MAX_NESTING)).is_ok());
+    assert_eq!(sig(&structs(types::MAX_NESTING + 1)), Err(TypeSigError::TooDeep(types::MAX_NESTING)));
+    // Dict entries nest like structs, arrays and structs are counted apart.
+    assert_eq!(sig(&format!("({}y{})", "a{s".repeat(types::MAX_NESTING), "}".repeat(types::MAX_NESTING))),
+               Err(TypeSigError::TooDeep(3 * types::MAX_NESTING - 1)));
+    assert!(sig(&format!("{}y{}", "a(".repeat(types::MAX_NESTING), ")".repeat(types::MAX_NESTING))).is_ok());
+    assert_eq!(sig(&"y".repeat(types::MAX_SIGNATURE_LEN + 1)), Err(TypeSigError::TooLong(types::MAX_SIGNATURE_LEN)));
+  }
+
+  #[test]
+  fn unusable_members() {
+    let info = r#"<node>
+      <interface name="org.example.Thing">
+        <method name="Good"><arg name="x" type="a{sv}" direction="in"/></method>
+        <method name="Bad"><arg name="x" type="s" direction="in"/><arg name="y" type="a{vs}" direction="in"/></method>
+        <signal name="Gone"><arg type="(i"/></signal>
+        <signal name="Here"><arg type="u"/></signal>
+        <property name="Broken" type="{sv}" access="read"/>
+        <property name="Fine" type="as" access="read"/>
+      </interface>
+    </node>"#
+                 .parse::<NodeInfo>()
+                 .unwrap();
+    let iface = &info.interfaces[0];
+    assert_eq!(iface.methods.iter().map(|m| &*m.name).collect::<Vec<_>>(), vec!["Good"]);
+    assert_eq!(iface.signals.iter().map(|s| &*s.name).collect::<Vec<_>>(), vec!["Here"]);
+    assert_eq!(iface.properties.iter().map(|p| &*p.name).collect::<Vec<_>>(), vec!["Fine"]);
+  }
+}
@@ -0,0 +1,174 @@
+use std::cell::RefCell;
+use std::time::SystemTime;
+
+use dbus::Message;
+use dbus::arg::messageitem::MessageItem;
+
+use format::Format;
+use node::{self, EmitsChanged};
+
+static PROPERTIES_IFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// Subscription of a signal file: the match rule installed for it and
+/// what is needed to pick and decode its signals from the connection.
+pub struct SignalWatch {
+  pub rule: String,
+  dest: String,
+  sender: Option<String>,
+  path: String,
+  iface: String,
+  member: String,
+  args: Vec<String>,
+  format: Format,
+}
+
+impl SignalWatch {
+  /// `owner` is the unique name currently owning `dest`, signals are always
+  /// delivered with the unique name of the emitter as their sender.
+  pub fn new(dest: &str, owner: Option<String>, path: &str, iface: &str, signal: &node::Signal, format: Format) -> SignalWatch {
+    SignalWatch {
+      rule: format!("type='signal',sender='{}',path='{}',interface='{}',member='{}'",
+                    dest,
+                    path,
+                    iface,
+                    signal.name),
+      dest: dest.to_owned(),
+      sender: owner,
+      path: path.to_owned(),
+      iface: iface.to_owned(),
+      member: signal.name.clone(),
+      args: signal.args.iter().map(|arg| arg.name.clone()).collect(),
+      format,
+    }
+  }
+
+  /// Follow the name to its new owner, signals of the old one are no longer from it.
+  /// Without an owner, `owner` is empty and matches no sender at all.
+  pub fn owner_changed(&mut self, name: &str, owner: &str) {
+    if name == self.dest {
+      self.sender = Some(owner.to_owned());
+    }
+  }
+
+  pub fn matches(&self, msg: &Message) -> bool {
+    self.sender.as_ref().is_none_or(|s| msg.sender().is_some_and(|sender| &*sender == s)) &&
+    msg.path().is_some_and(|path| *path == self.path) && msg.interface().is_some_and(|iface| *iface == self.iface) &&
+    msg.member().is_some_and(|member| *member == self.member)
+  }
+
+  pub fn format(&self, msg: &Message) -> Vec<u8> {
+    let names = self.args.iter().map(|name| &**name).collect::<Vec<_>>();
+    self.format.signal(&names, &msg.get_items())
+  }
+}
+
+/// Cached value of a property file, kept as long as its `EmitsChangedSignal`
+/// annotation allows, and its subscription to `PropertiesChanged`.
+pub struct PropertyWatch {
+  /// Match rule, none for a constant property.
+  pub rule: Option<String>,
+  dest: String,
+  sender: Option<String>,
+  path: String,
+  iface: String,
+  name: String,
+  emits: EmitsChanged,
+  value: RefCell<Option<MessageItem>>,
+  /// When the value last changed, as the modification time of the file.
+  pub changed: SystemTime,
+}
+
+impl PropertyWatch {
+  /// `owner` is as for `SignalWatch::new`. Properties of an interface share the rule.
+  ///
+  /// Properties that don't emit the signal can't be watched, `None` then.
+  pub fn new(dest: &str, owner: Option<String>, path: &str, iface: &str, name: &str, emits: EmitsChanged) -> Option<PropertyWatch> {
+    let rule = match emits {
+      EmitsChanged::False => return None,
+      EmitsChanged::Const => None,
+      EmitsChanged::True | EmitsChanged::Invalidates => {
+        Some(format!("type='signal',sender='{}',path='{}',interface='{}',member='PropertiesChanged',arg0='{}'",
+                     dest,
+                     path,
+                     PROPERTIES_IFACE,
+                     iface))
+      }
+    };
+
+    Some(PropertyWatch {
+      rule,
+      dest: dest.to_owned(),
+      sender: owner,
+      path: path.to_owned(),
+      iface: iface.to_owned(),
+      name: name.to_owned(),
+      emits,
+      value: RefCell::new(None),
+      changed: SystemTime::now(),
+    })
+  }
+
+  /// The cached value, if any.
+  pub fn value(&self) -> Option<MessageItem> {
+    self.value.borrow().clone()
+  }
+
+  /// Cache a value just read from the bus.
+  pub fn keep(&self, value: &MessageItem) {
+    *self.value.borrow_mut() = Some(value.clone());
+  }
+
+  /// Drop the cached value, the next read asks the bus again.
+  pub fn forget(&self) {
+    self.value.borrow_mut().take();
+  }
+
+  /// Follow the name to its new owner, as `SignalWatch::owner_changed` does.
+  pub fn owner_changed(&mut self, name: &str, owner: &str) {
+    if name == self.dest {
+      self.sender = Some(owner.to_owned());
+    }
+  }
+
+  /// Take the value of the property from a `PropertiesChanged` signal, if it's about
+  /// it. With `invalidates` the value is only dropped, to be asked for again on the
+  /// next read. Returns whether the property changed.
+  pub fn update(&mut self, msg: &Message) -> bool {
+    if self.emits == EmitsChanged::Const || self.sender.as_ref().is_some_and(|s| msg.sender().is_none_or(|sender| &*sender != s)) ||
+       msg.path().is_none_or(|path| *path != self.path) || msg.interface().is_none_or(|iface| &*iface != PROPERTIES_IFACE) ||
+       msg.member().is_none_or(|member| &*member != "PropertiesChanged") {
+      return false;
+    }
+
+    let items = msg.get_items();
+    match items.first() {
+      Some(MessageItem::Str(iface)) if *iface == self.iface => {}
+      _ => return false,
+    }
+
+    let changed = match items.get(1) {
+      Some(MessageItem::Dict(entries)) => {
+        entries.iter()
+               .filter_map(|entry| {
+                 match *entry {
+                   (MessageItem::Str(ref name), MessageItem::Variant(ref value)) if *name == self.name => Some((**value).clone()),
+                   _ => None,
+                 }
+               })
+               .next()
+      }
+      _ => None,
+    };
+    let invalidated = match items.get(2) {
+      Some(MessageItem::Array(names)) => names.iter().any(|name| *name == MessageItem::Str(self.name.clone())),
+      _ => false,
+    };
+
+    if changed.is_none() && !invalidated {
+      return false;
+    }
+    *self.value.borrow_mut() = if self.emits == EmitsChanged::True { changed } else { None };
+    self.changed = SystemTime::now();
+    true
+  }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use filter::Pattern;
+
+/// Milliseconds to wait for a reply unless told otherwise.
+pub const DEFAULT_TIMEOUT_MS: i32 = 1000;
+
+/// How long to wait for replies, in milliseconds.
+///
+/// A call of a method waits as long as set for that method, then as long as set for
+/// the last destination pattern matching its bus name, then the default.
+pub struct Timeouts {
+  default: i32,
+  dests: Vec<(Pattern, i32)>,
+  calls: HashMap<String, i32>,
+}
+
+impl Default for Timeouts {
+  fn default() -> Timeouts {
+    Timeouts {
+      default: DEFAULT_TIMEOUT_MS,
+      dests: Vec::new(),
+      calls: HashMap::new(),
+    }
+  }
+}
+
+impl Timeouts {
+  pub fn set_default(&mut self, ms: i32) {
+    self.default = ms;
+  }
+
+  /// Add an override for destinations matching a glob, or a regex as `re:PATTERN`.
+  pub fn set_dest(&mut self, pattern: &str, ms: i32) -> Result<(), String> {
+    self.dests.push((Pattern::new(pattern)?, ms));
+    Ok(())
+  }
+
+  /// Add an override from `NAME=MS`.
+  pub fn parse_dest(&mut self, spec: &str) -> Result<(), String> {
+    match spec.rfind('=') {
+      Some(eq) => self.set_dest(&spec[..eq], parse_ms(&spec[eq + 1..])?),
+      None => Err(format!("expected NAME=MS, got `{}`", spec)),
+    }
+  }
+
+  pub fn for_dest(&self, dest: &str) -> i32 {
+    self.dests.iter().rev().find(|&(p, _)| p.matches(dest)).map_or(self.default, |&(_, ms)| ms)
+  }
+
+  /// Override the timeout of a method, `key` tells methods on different objects apart.
+  pub fn set_call(&mut self, key: &str, ms: i32) {
+    self.calls.insert(key.to_owned(), ms);
+  }
+
+  pub fn clear_call(&mut self, key: &str) {
+    self.calls.remove(key);
+  }
+
+  pub fn for_call(&self, key: &str, dest: &str) -> i32 {
+    self.calls.get(key).cloned().unwrap_or_else(|| self.for_dest(dest))
+  }
+}
+
+/// A timeout in milliseconds, which has to be positive.
+pub fn parse_ms(ms: &str) -> Result<i32, String> {
+  match ms.trim().parse() {
+    Ok(ms) if ms > 0 => Ok(ms),
+    _ => Err(format!("bad timeout `{}`, expected a positive number of milliseconds", ms)),
+  }
+}
@@ -0,0 +1,616 @@
+//! Text form of D-Bus values, in the style of GVariant text format.
+//!
+//! * booleans are `true` and `false`, numbers are plain decimals, doubles always
+//!   have a fraction or an exponent, strings are double quoted with C escapes;
+//! * object paths and signatures are strings prefixed with `objectpath` and `signature`;
+//! * byte arrays are byte strings like `b"abc\x00"`;
+//! * arrays are `[a, b]`, dicts are `{k: v}`, structs are `(a, b)` or `(a,)`;
+//! * variants carry their type: `<@s "text">`, `<@a{sv} {}>`;
+//! * unix fds are shown as `handle N`, but can't be parsed back.
+//!
+//! A value alone in a file, like a property value, is parsed and rendered with
+//! strings, object paths and signatures left bare, so `cat` shows a word as is.
+//! Strings that wouldn't read back the same bare are quoted: empty ones, and those
+//! starting with a quote or holding whitespace or commas.
+
+use std::fmt::Write;
+use std::os::unix::io::AsRawFd;
+
+use dbus;
+use dbus::arg::messageitem::{MessageItem, MessageItemArray, MessageItemDict};
+use node::types::{self, Basic, Full};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseError {
+  InvalidValue(usize),
+  UnexpectedEnd,
+  TrailingChars(usize),
+  UnsupportedType(String),
+  ArgumentCount(usize, usize),
+}
+
+/// Render a single value, like a property value, as a line of text.
+pub fn format(item: &MessageItem) -> String {
+  let mut out = match *item {
+    MessageItem::Str(ref s) if reads_bare(s) => s.clone(),
+    MessageItem::ObjectPath(ref p) => (**p).to_owned(),
+    MessageItem::Signature(ref s) => (**s).to_owned(),
+    ref item => render(item),
+  };
+  out.push('\n');
+  out
+}
+
+/// Whether a string left bare is parsed back as itself.
+fn reads_bare(s: &str) -> bool {
+  !s.is_empty() && !s.starts_with('"') && !s.starts_with('\'') && !s.contains(|c: char| c.is_whitespace() || c == ',')
+}
+
+/// Render a method reply: a single value as with `format`, several values one per line.
+pub fn format_args(items: &[MessageItem]) -> String {
+  match items.len() {
+    1 => format(&items[0]),
+    _ => {
+      items.iter().fold(String::new(), |mut out, item| {
+        out.push_str(&render(item));
+        out.push('\n');
+        out
+      })
+    }
+  }
+}
+
+/// Render a signal body as a single line of comma separated values.
+pub fn format_line(items: &[MessageItem]) -> String {
+  let mut out = items.iter().map(render).collect::<Vec<_>>().join(", ");
+  out.push('\n');
+  out
+}
+
+/// Render any value in its full text form.
+pub fn render(item: &MessageItem) -> String {
+  let mut out = String::new();
+  render_into(&mut out, item);
+  out
+}
+
+fn render_into(out: &mut String, item: &MessageItem) {
+  match *item {
+    MessageItem::Array(ref items) if &**items.signature() == "ay" => {
+      let bytes = items.iter()
+                       .filter_map(|i| {
+                         match *i {
+                           MessageItem::Byte(b) => Some(b),
+                           _ => None,
+                         }
+                       })
+                       .collect::<Vec<_>>();
+      render_bytes(out, &bytes);
+    }
+    MessageItem::Array(ref items) => {
+      out.push('[');
+      render_list(out, items);
+      out.push(']');
+    }
+    MessageItem::Dict(ref entries) => {
+      out.push('{');
+      for (no, (key, value)) in entries.iter().enumerate() {
+        if no > 0 {
+          out.push_str(", ");
+        }
+        render_into(out, key);
+        out.push_str(": ");
+        render_into(out, value);
+      }
+      out.push('}');
+    }
+    MessageItem::Struct(ref items) => {
+      out.push('(');
+      render_list(out, items);
+      if items.len() == 1 {
+        out.push(',');
+      }
+      out.push(')');
+    }
+    MessageItem::Variant(ref inner) => {
+      let _ = write!(out, "<@{} ", inner.signature());
+      render_into(out, inner);
+      out.push('>');
+    }
+    MessageItem::ObjectPath(ref p) => {
+      out.push_str("objectpath ");
+      render_str(out, p);
+    }
+    MessageItem::Signature(ref s) => {
+      out.push_str("signature ");
+      render_str(out, s);
+    }
+    MessageItem::Str(ref s) => render_str(out, s),
+    MessageItem::Bool(b) => out.push_str(if b { "true" } else { "false" }),
+    MessageItem::Byte(b) => out.push_str(&b.to_string()),
+    MessageItem::Int16(i) => out.push_str(&i.to_string()),
+    MessageItem::Int32(i) => out.push_str(&i.to_string()),
+    MessageItem::Int64(i) => out.push_str(&i.to_string()),
+    MessageItem::UInt16(i) => out.push_str(&i.to_string()),
+    MessageItem::UInt32(i) => out.push_str(&i.to_string()),
+    MessageItem::UInt64(i) => out.push_str(&i.to_string()),
+    // Debug format keeps the fraction of whole numbers: `1.0`, not `1`.
+    MessageItem::Double(d) => {
+      let _ = write!(out, "{:?}", d);
+    }
+    MessageItem::UnixFd(ref fd) => {
+      let _ = write!(out, "handle {}", fd.as_raw_fd());
+    }
+  }
+}
+
+fn render_list(out: &mut String, items: &[MessageItem]) {
+  for (no, item) in items.iter().enumerate() {
+    if no > 0 {
+      out.push_str(", ");
+    }
+    render_into(out, item);
+  }
+}
+
+fn render_str(out: &mut String, s: &str) {
+  out.push('"');
+  for c in s.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if c.is_control() => {
+        let _ = write!(out, "\\u{:04x}", c as u32);
+      }
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+}
+
+fn render_bytes(out: &mut String, bytes: &[u8]) {
+  out.push_str("b\"");
+  for &b in bytes {
+    match b {
+      b'"' => out.push_str("\\\""),
+      b'\\' => out.push_str("\\\\"),
+      0x20..=0x7e => out.push(b as char),
+      b => {
+        let _ = write!(out, "\\x{:02x}", b);
+      }
+    }
+  }
+  out.push('"');
+}
+
+/// Parse a single value, like one written to a property file.
+///
+/// A single trailing newline is ignored, so `echo value > file` works, and
+/// strings, object paths and signatures may be written bare.
+pub fn parse(text: &str, typesig: &Full) -> Result<MessageItem, ParseError> {
+  let text = text.strip_suffix('\n').unwrap_or(text);
+
+  if let Full::Basic(basic) = *typesig {
+    let start = text.trim_start();
+    let quoted = start.starts_with('"') || start.starts_with('\'');
+    match basic {
+      Basic::String if !quoted => return Ok(MessageItem::Str(text.to_owned())),
+      Basic::ObjPath if !quoted && !start.starts_with("objectpath") => return object_path(text.trim(), 0),
+      Basic::TypeSig if !quoted && !start.starts_with("signature") => return signature(text.trim(), 0),
+      _ => (),
+    }
+  }
+
+  let mut parser = Parser::new(text);
+  let value = parser.value(typesig)?;
+  parser.end()?;
+  Ok(value)
+}
+
+/// Parse method arguments against the signatures of the input arguments.
+///
+/// A single argument is parsed as with `parse`, several are separated by commas or line breaks.
+pub fn parse_args(text: &str, typesigs: &[&Full]) -> Result<Vec<MessageItem>, ParseError> {
+  if typesigs.len() == 1 {
+    return parse(text, typesigs[0]).map(|v| vec![v]);
+  }
+
+  let mut parser = Parser::new(text);
+  let mut items = Vec::with_capacity(typesigs.len());
+  for (no, sig) in typesigs.iter().enumerate() {
+    if no > 0 && !parser.separator() {
+      return Err(ParseError::ArgumentCount(typesigs.len(), no));
+    }
+    items.push(parser.value(sig)?);
+  }
+
+  parser.separator();
+  parser.end()?;
+  Ok(items)
+}
+
+fn object_path(s: &str, pos: usize) -> Result<MessageItem, ParseError> {
+  dbus::Path::new(s).map(MessageItem::ObjectPath).map_err(|_| ParseError::InvalidValue(pos))
+}
+
+fn signature(s: &str, pos: usize) -> Result<MessageItem, ParseError> {
+  types::parse_signature(s).map_err(|e| ParseError::InvalidValue(pos + e.position()))?;
+  dbus::Signature::new(s).map(MessageItem::Signature).map_err(|_| ParseError::InvalidValue(pos))
+}
+
+struct Parser<'a> {
+  text: &'a str,
+  pos: usize,
+}
+
+impl<'a> Parser<'a> {
+  fn new(text: &'a str) -> Parser<'a> {
+    Parser {
+      text,
+      pos: 0,
+    }
+  }
+
+  fn rest(&self) -> &'a str {
+    &self.text[self.pos..]
+  }
+
+  fn skip_ws(&mut self) {
+    let rest = self.rest();
+    self.pos += rest.len() - rest.trim_start().len();
+  }
+
+  fn peek(&mut self) -> Option<char> {
+    self.skip_ws();
+    self.rest().chars().next()
+  }
+
+  fn eat(&mut self, c: char) -> bool {
+    if self.peek() == Some(c) {
+      self.pos += c.len_utf8();
+      true
+    } else {
+      false
+    }
+  }
+
+  fn expect(&mut self, c: char) -> Result<(), ParseError> {
+    match self.peek() {
+      Some(found) if found == c => {
+        self.pos += c.len_utf8();
+        Ok(())
+      }
+      Some(_) => Err(ParseError::InvalidValue(self.pos)),
+      None => Err(ParseError::UnexpectedEnd),
+    }
+  }
+
+  fn keyword(&mut self, word: &str) -> bool {
+    self.skip_ws();
+    if self.rest().starts_with(word) {
+      self.pos += word.len();
+      true
+    } else {
+      false
+    }
+  }
+
+  /// Skip a comma or a line break between method arguments.
+  fn separator(&mut self) -> bool {
+    let rest = self.rest();
+    let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+    if trimmed.starts_with('\n') {
+      self.pos += rest.len() - trimmed.len() + 1;
+      self.eat(',');
+      true
+    } else {
+      self.eat(',')
+    }
+  }
+
+  fn end(&mut self) -> Result<(), ParseError> {
+    match self.peek() {
+      None => Ok(()),
+      Some(_) => Err(ParseError::TrailingChars(self.pos)),
+    }
+  }
+
+  /// A run of characters that may make up a number or a bare word.
+  fn token(&mut self) -> Result<(&'a str, usize), ParseError> {
+    self.skip_ws();
+    let start = self.pos;
+    let rest = self.rest();
+    let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '+' || c == '.' || c == '_'))
+                  .unwrap_or(rest.len());
+    if len == 0 {
+      return Err(if rest.is_empty() { ParseError::UnexpectedEnd } else { ParseError::InvalidValue(start) });
+    }
+    self.pos += len;
+    Ok((&rest[..len], start))
+  }
+
+  fn value(&mut self, typesig: &Full) -> Result<MessageItem, ParseError> {
+    match *typesig {
+      Full::Basic(basic) => self.basic(basic),
+      Full::Variant => self.variant(),
+      Full::Array(ref elem) => self.array(elem),
+      Full::Struct(ref fields) => {
+        self.expect('(')?;
+        let mut items = Vec::with_capacity(fields.len());
+        for (no, field) in fields.iter().enumerate() {
+          if no > 0 {
+            self.expect(',')?;
+          }
+          items.push(self.value(field)?);
+        }
+        self.eat(',');
+        self.expect(')')?;
+        Ok(MessageItem::Struct(items))
+      }
+      Full::Dict(..) => Err(ParseError::UnsupportedType(typesig.to_string())),
+    }
+  }
+
+  fn basic(&mut self, basic: Basic) -> Result<MessageItem, ParseError> {
+    match basic {
+      Basic::String => self.string().map(MessageItem::Str),
+      Basic::ObjPath => {
+        self.keyword("objectpath");
+        let pos = self.pos;
+        self.string().and_then(|s| object_path(&s, pos))
+      }
+      Basic::TypeSig => {
+        self.keyword("signature");
+        let pos = self.pos;
+        self.string().and_then(|s| signature(&s, pos))
+      }
+      Basic::Bool => {
+        match self.token()? {
+          ("true", _) => Ok(MessageItem::Bool(true)),
+          ("false", _) => Ok(MessageItem::Bool(false)),
+          (_, pos) => Err(ParseError::InvalidValue(pos)),
+        }
+      }
+      Basic::Double => {
+        let (token, pos) = self.token()?;
+        token.parse().map(MessageItem::Double).map_err(|_| ParseError::InvalidValue(pos))
+      }
+      Basic::UnixFd => Err(ParseError::UnsupportedType(basic.to_string())),
+      _ => {
+        let (token, pos) = self.token()?;
+        let invalid = |_| ParseError::InvalidValue(pos);
+        let (digits, radix) = token.strip_prefix("0x").map_or((token, 10), |hex| (hex, 16));
+        match basic {
+          Basic::Byte => u8::from_str_radix(digits, radix).map(MessageItem::Byte).map_err(invalid),
+          Basic::Int16 => i16::from_str_radix(digits, radix).map(MessageItem::Int16).map_err(invalid),
+          Basic::UInt16 => u16::from_str_radix(digits, radix).map(MessageItem::UInt16).map_err(invalid),
+          Basic::Int32 => i32::from_str_radix(digits, radix).map(MessageItem::Int32).map_err(invalid),
+          Basic::UInt32 => u32::from_str_radix(digits, radix).map(MessageItem::UInt32).map_err(invalid),
+          Basic::Int64 => i64::from_str_radix(digits, radix).map(MessageItem::Int64).map_err(invalid),
+          _ => u64::from_str_radix(digits, radix).map(MessageItem::UInt64).map_err(invalid),
+        }
+      }
+    }
+  }
+
+  fn array(&mut self, elem: &Full) -> Result<MessageItem, ParseError> {
+    let pos = self.pos;
+    let invalid = |_| ParseError::InvalidValue(pos);
+
+    if let Full::Basic(Basic::Byte) = *elem {
+      if self.keyword("b") {
+        let bytes = self.quoted(true)?;
+        let sig = dbus::Signature::from("ay");
+        return MessageItemArray::new(bytes.into_iter().map(MessageItem::Byte).collect(), sig).map(MessageItem::Array).map_err(invalid);
+      }
+    }
+
+    let (entry, close) = match *elem {
+      Full::Dict(key, ref value) => {
+        self.expect('{')?;
+        (Some((Full::Basic(key), &**value)), '}')
+      }
+      _ => {
+        self.expect('[')?;
+        (None, ']')
+      }
+    };
+
+    let mut items = Vec::new();
+    let mut entries = Vec::new();
+    while !self.eat(close) {
+      if !items.is_empty() || !entries.is_empty() {
+        self.expect(',')?;
+        // a trailing comma before the closing bracket is fine
+        if self.eat(close) {
+          break;
+        }
+      }
+
+      match entry {
+        Some((ref key, value)) => {
+          let key = self.value(key)?;
+          self.expect(':')?;
+          entries.push((key, self.value(value)?));
+        }
+        None => items.push(self.value(elem)?),
+      }
+    }
+
+    match entry {
+      Some((ref key, value)) => {
+        let (key, value) = (dbus::Signature::from(key.to_string()), dbus::Signature::from(value.to_string()));
+        MessageItemDict::new(entries, key, value).map(MessageItem::Dict).map_err(invalid)
+      }
+      None => MessageItemArray::new(items, dbus::Signature::from(format!("a{}", elem))).map(MessageItem::Array).map_err(invalid),
+    }
+  }
+
+  /// Parse `<@type value>`, or `<value>` for values whose type is obvious from the text.
+  fn variant(&mut self) -> Result<MessageItem, ParseError> {
+    self.expect('<')?;
+
+    let typesig = if self.eat('@') {
+      let rest = self.rest();
+      let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+      let pos = self.pos;
+      self.pos += len;
+      rest[..len].parse::<Full>().map_err(|e| ParseError::InvalidValue(pos + e.position()))?
+    } else {
+      self.guess_type()?
+    };
+
+    let value = self.value(&typesig)?;
+    self.expect('>')?;
+    Ok(MessageItem::Variant(Box::new(value)))
+  }
+
+  fn guess_type(&mut self) -> Result<Full, ParseError> {
+    let next = self.peek();
+    let pos = self.pos;
+    let rest = self.rest();
+
+    Ok(Full::Basic(match next {
+      Some('"') | Some('\'') => Basic::String,
+      _ if rest.starts_with("true") || rest.starts_with("false") => Basic::Bool,
+      _ if rest.starts_with("objectpath") => Basic::ObjPath,
+      _ if rest.starts_with("signature") => Basic::TypeSig,
+      Some(c) if c.is_ascii_digit() || c == '-' || c == '+' => {
+        let (token, _) = self.token()?;
+        self.pos = pos;
+        if token.contains('.') || token.contains('e') || token.contains('E') {
+          Basic::Double
+        } else {
+          Basic::Int32
+        }
+      }
+      Some(_) => return Err(ParseError::InvalidValue(pos)),
+      None => return Err(ParseError::UnexpectedEnd),
+    }))
+  }
+
+  fn string(&mut self) -> Result<String, ParseError> {
+    let pos = self.pos;
+    let bytes = self.quoted(false)?;
+    String::from_utf8(bytes).map_err(|_| ParseError::InvalidValue(pos))
+  }
+
+  /// Parse a quoted string with C escapes, `\xNN` escapes are only allowed in byte strings.
+  fn quoted(&mut self, bytes: bool) -> Result<Vec<u8>, ParseError> {
+    let quote = match self.peek() {
+      Some(c @ '"') | Some(c @ '\'') => c,
+      Some(_) => return Err(ParseError::InvalidValue(self.pos)),
+      None => return Err(ParseError::UnexpectedEnd),
+    };
+    self.pos += 1;
+
+    let mut out = Vec::new();
+    let mut chars = self.rest().char_indices();
+    while let Some((off, c)) = chars.next() {
+      let pos = self.pos + off;
+      let c = match c {
+        c if c == quote => {
+          self.pos = pos + 1;
+          return Ok(out);
+        }
+        '\\' => {
+          match chars.next() {
+            Some((_, 'n')) => '\n',
+            Some((_, 'r')) => '\r',
+            Some((_, 't')) => '\t',
+            Some((_, '0')) => '\0',
+            Some((_, 'x')) if bytes => {
+              let hex = chars.by_ref().take(2).map(|(_, c)| c).collect::<String>();
+              out.push(u8::from_str_radix(&hex, 16).map_err(|_| ParseError::InvalidValue(pos))?);
+              continue;
+            }
+            Some((_, 'u')) if !bytes => {
+              let hex = chars.by_ref().take(4).map(|(_, c)| c).collect::<String>();
+              u32::from_str_radix(&hex, 16)
+                .ok()
+                .and_then(::std::char::from_u32)
+                .ok_or(ParseError::InvalidValue(pos))?
+            }
+            Some((_, c)) if c == '\\' || c == '"' || c == '\'' => c,
+            Some(_) => return Err(ParseError::InvalidValue(pos)),
+            None => return Err(ParseError::UnexpectedEnd),
+          }
+        }
+        c => c,
+      };
+
+      out.extend_from_slice(c.to_string().as_bytes());
+    }
+
+    Err(ParseError::UnexpectedEnd)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use node::types::Full;
+
+  fn round_trip(sig: &str, text: &str) {
+    let value = parse(text, &sig.parse::<Full>().unwrap()).unwrap();
+    assert_eq!(&*value.signature(), sig);
+    assert_eq!(render(&value), text);
+  }
+
+  fn format_round_trip(text: &str) {
+    let value = MessageItem::Str(text.to_owned());
+    let line = format(&value);
+    assert_eq!(parse(&line, &"s".parse::<Full>().unwrap()), Ok(value), "{:?}", line);
+  }
+
+  #[test]
+  fn bare_strings() {
+    assert_eq!(format(&MessageItem::Str("word".to_owned())), "word\n");
+    assert_eq!(format(&MessageItem::Str("a b".to_owned())), "\"a b\"\n");
+    for text in &["word", "", "\"q\" x", "'q'", "\"", "a b", " lead", "trail ", "a,b", "two\nlines", "tab\there", "ünï"] {
+      format_round_trip(text);
+    }
+  }
+
+  #[test]
+  fn byte_arrays() {
+    round_trip("ay", r#"b"abc\x00\"""#);
+    round_trip("ay", r#"b"""#);
+  }
+
+  #[test]
+  fn dicts() {
+    round_trip("a{sv}", r#"{"a": <@i 1>, "b": <@as ["x", "y"]>}"#);
+    round_trip("a{sv}", "{}");
+    round_trip("a{ua{sb}}", r#"{1: {"x": true}}"#);
+  }
+
+  #[test]
+  fn structs() {
+    round_trip("(is)", r#"(1, "x")"#);
+    round_trip("(i)", "(1,)");
+  }
+
+  #[test]
+  fn variants() {
+    round_trip("v", r#"<@s "x">"#);
+    round_trip("v", "<@ay b\"a\">");
+    round_trip("v", r#"<@a{sv} {"a": <@d 1.5>}>"#);
+  }
+
+  #[test]
+  fn object_paths() {
+    round_trip("ao", r#"[objectpath "/a", objectpath "/a/b"]"#);
+    round_trip("ao", "[]");
+  }
+
+  #[test]
+  fn signatures() {
+    round_trip("ag", r#"[signature "a{sv}", signature "(ii)"]"#);
+    assert_eq!(format(&parse("a{sv}", &"g".parse::<Full>().unwrap()).unwrap()), "a{sv}\n");
+    assert!(parse("a{", &"g".parse::<Full>().unwrap()).is_err());
+  }
+}
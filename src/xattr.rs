@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+
+use inode::NodeKind;
+use node::{Access, Direction, Interface, Method, Property, Signal};
+
+/// Extended attributes of a node as name and value pairs, in the order they are listed.
+pub type Attrs = Vec<(String, String)>;
+
+static PREFIX: &str = "user.dbus.";
+
+/// Timeout of a method, the one attribute that can be set.
+pub static TIMEOUT: &str = "user.dbus.timeout";
+
+fn attr(attrs: &mut Attrs, name: &str, value: &str) {
+  attrs.push((format!("{}{}", PREFIX, name), value.to_owned()));
+}
+
+fn annotations(attrs: &mut Attrs, annotations: &BTreeMap<String, String>) {
+  for (name, value) in annotations {
+    attr(attrs, &format!("annotation.{}", name), value);
+  }
+}
+
+/// `user.dbus.kind` of every node.
+pub fn kind(kind: NodeKind) -> Attrs {
+  let name = match kind {
+    NodeKind::Bus => "bus",
+    NodeKind::Destination => "destination",
+    NodeKind::ObjectPath => "object",
+    NodeKind::Interface => "interface",
+    NodeKind::Method => "method",
+    NodeKind::Signal => "signal",
+    NodeKind::Property => "property",
+    NodeKind::Properties => "properties",
+    NodeKind::Annotation => "annotation",
+    NodeKind::Confirm => "confirm",
+  };
+  let mut attrs = Vec::new();
+  attr(&mut attrs, "kind", name);
+  attrs
+}
+
+pub fn interface(attrs: &mut Attrs, iface: &Interface) {
+  annotations(attrs, &iface.annotations);
+}
+
+pub fn property(attrs: &mut Attrs, prop: &Property) {
+  attr(attrs, "type", &prop.typesig.to_string());
+  attr(attrs,
+       "access",
+       match prop.access {
+         Access::Read => "read",
+         Access::Write => "write",
+         Access::ReadWrite => "readwrite",
+       });
+  annotations(attrs, &prop.annotations);
+}
+
+/// Arguments are numbered in order, as `user.dbus.arg.N.name`, `.type` and `.direction`.
+pub fn method(attrs: &mut Attrs, method: &Method) {
+  for (no, (arg, dir)) in method.args.iter().enumerate() {
+    attr(attrs, &format!("arg.{}.name", no), &arg.name);
+    attr(attrs, &format!("arg.{}.type", no), &arg.typesig.to_string());
+    attr(attrs,
+         &format!("arg.{}.direction", no),
+         match *dir {
+           Direction::In => "in",
+           Direction::Out => "out",
+         });
+  }
+  annotations(attrs, &method.annotations);
+}
+
+/// The type of a signal is the signature of its whole body.
+pub fn signal(attrs: &mut Attrs, signal: &Signal) {
+  let sig = signal.args.iter().map(|arg| arg.typesig.to_string()).collect::<String>();
+  attr(attrs, "type", &sig);
+  for (no, arg) in signal.args.iter().enumerate() {
+    attr(attrs, &format!("arg.{}.name", no), &arg.name);
+    attr(attrs, &format!("arg.{}.type", no), &arg.typesig.to_string());
+  }
+  annotations(attrs, &signal.annotations);
+}
+
+/// Milliseconds a call waits for its reply, in `user.dbus.timeout`.
+pub fn timeout(attrs: &mut Attrs, ms: i32) {
+  attr(attrs, "timeout", &ms.to_string());
+}
+
+/// Name and message of the last D-Bus error on a file, in `user.dbus.last_error`.
+pub fn last_error(attrs: &mut Attrs, err: &str) {
+  attr(attrs, "last_error", err);
+}
+
+/// How to get a guarded method called, in `user.dbus.guarded`.
+pub fn guarded(attrs: &mut Attrs, member: &str) {
+  attr(attrs,
+       "guarded",
+       &format!("calls are refused, write the token read from {0}.confirm back to {0}.confirm to allow one",
+                member));
+}